@@ -15,13 +15,23 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
 use crate::asyncfile::FileHandler;
-use crate::handle::{FHandler, FileOptionType, FmtHandler};
+use crate::config::{self, Config, ConfigError, Settings};
+use crate::handle::{async_flush_sinks, async_write_sinks, AsyncSink, AsyncSinkRef, ConsoleSink, FHandler, FileOption, FileOptionType, FmtHandler};
+use crate::queue::{self, Ack, Message, Queue};
 use crate::tklog::asynclog;
 use crate::trie::Trie;
-use crate::{arguments_to_string, l2tk, log_fmt, AttrFormat, Format, LogContext, LogOption, LogOptionConst, OptionTrait, LEVEL, MODE, PRINTMODE, TKLOG2ASYNC_LOG};
-use tokio::sync::mpsc;
+use crate::{arguments_to_string, env_mod_directives, l2tk, log_fmt, record_fields, AttrFormat, Directive, ErrorStats, FieldValue, FmtRecord, Format, LogContext, LogError, LogOption, LogOptionConst, OptionTrait, Reporter, SinkOption, FAILURE, LEVEL, MODE, OVERFLOW, PRINTMODE, TKLOG2ASYNC_LOG};
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
+use tokio::sync::oneshot;
+
+type SharedHandler = Arc<tokio::sync::Mutex<FHandler>>;
 
 /// this is the tklog encapsulated Logger whose File operations
 /// are based on tokio, Therefore, it supports asynchronous scenarios
@@ -45,36 +55,29 @@ use tokio::sync::mpsc;
 /// };
 /// ```
 pub struct Logger {
-    sender: mpsc::UnboundedSender<(LEVEL, String, String)>,
+    queue: Queue,
     fmthandle: FmtHandler,
-    filehandle: (String, FHandler),
+    filehandle: (String, SharedHandler),
     mutex: tokio::sync::Mutex<u32>,
     pub mode: PRINTMODE,
     modmap: Trie<(LogOptionConst, String)>,
-    fmap: HashMap<String, FHandler>,
+    fmap: HashMap<String, SharedHandler>,
     custom_handler: Option<fn(&LogContext) -> bool>,
     separator: String,
-    levels: Option<[Option<(LogOption, String)>; 7]>,
+    levels: Option<[Option<(LogOptionConst, String)>; 7]>,
     // levelfmt: Option<Box<dyn Fn(LEVEL) -> String + Send + Sync>>,
     // timefmt: Option<Box<dyn Fn() -> (String, String, String) + Send + Sync>>,
     attrfmt: AttrFormat,
+    config_path: Option<PathBuf>,
+    errors: Reporter,
 }
 
 impl Logger {
     pub fn new() -> Self {
-        let (sender, mut receiver) = mpsc::unbounded_channel();
-        tokio::spawn(async move {
-            while let Some(message) = receiver.recv().await {
-                let (level, module, msg) = message;
-                let m1: String = module;
-                let m2: String = msg;
-                crate::async_log!(level, m1.as_str(), m2.as_str());
-            }
-        });
-        Logger {
-            sender,
+        let mut log = Logger {
+            queue: Self::spawn_queue(0, OVERFLOW::Block),
             fmthandle: FmtHandler::new(),
-            filehandle: ("".to_string(), FHandler::new()),
+            filehandle: ("".to_string(), Arc::new(tokio::sync::Mutex::new(FHandler::new()))),
             mutex: tokio::sync::Mutex::new(0),
             mode: PRINTMODE::DELAY,
             modmap: Trie::new(),
@@ -85,315 +88,213 @@ impl Logger {
             // levelfmt: None,
             // timefmt: None,
             attrfmt: AttrFormat::new(),
-        }
+            config_path: None,
+            errors: Reporter::default(),
+        };
+        log.reset_options();
+        log
     }
 
-    pub async fn print(&mut self, level: LEVEL, module: &str, message: &str) {
-        let mut console = String::new();
-        let mut msg = String::new();
-        let mut is_bodyfmt = false;
-
-        if let Some(f) = &self.attrfmt.bodyfmt {
-            is_bodyfmt = true;
-            msg = f(level, message.to_string());
+    // Restores what a config file can set to the defaults, including module levels from RUST_LOG.
+    fn reset_options(&mut self) {
+        self.fmthandle = FmtHandler::new();
+        self.separator = "".to_string();
+        self.modmap = Trie::new();
+        self.fmap.clear();
+        self.levels = None;
+        for d in env_mod_directives() {
+            self.set_mod_level(d.module.as_deref().unwrap_or_default(), d.level);
         }
+    }
 
-        if self.modmap.len() > 0 {
-            if let Some(mm) = self.modmap.get(module) {
-                let (lo, filename) = mm;
-                let mut is_mod_console = self.fmthandle.get_console();
-                let mut is_consolefmt = false;
-                if let Some(cs) = lo.console {
-                    is_mod_console = cs;
-                    if is_mod_console {
-                        if let Some(f) = &self.attrfmt.console_bodyfmt {
-                            is_consolefmt = true;
-                            console = f(level, message.to_string());
-                        }
-                    }
-                }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self
-                            .filehandle
-                            .1
-                            .async_print(
-                                is_mod_console,
-                                if is_mod_console {
-                                    if is_consolefmt {
-                                        console.as_str()
-                                    } else {
-                                        ""
-                                    }
-                                } else {
-                                    ""
-                                },
-                                if is_bodyfmt { msg.as_str() } else { message },
-                            )
-                            .await;
-                    } else {
-                        if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm
-                                .async_print(
-                                    is_mod_console,
-                                    if is_mod_console {
-                                        if is_consolefmt {
-                                            console.as_str()
-                                        } else {
-                                            ""
-                                        }
-                                    } else {
-                                        ""
-                                    },
-                                    if is_bodyfmt { msg.as_str() } else { message },
-                                )
-                                .await;
+    // The writer owns its runtime so that it outlives the runtime that first used the logger.
+    fn spawn_queue(capacity: usize, overflow: OVERFLOW) -> Queue {
+        Queue::spawn(capacity, overflow, |receiver, dropped, closed| {
+            let rt = match tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build() {
+                Ok(rt) => rt,
+                Err(_) => return,
+            };
+            queue::run(
+                receiver,
+                dropped,
+                closed,
+                |level, module, msg| rt.block_on(print(level, module, msg)),
+                |n| {
+                    let out = {
+                        let mut log = global();
+                        let s = log.fmt("tklog", LEVEL::Warn, "", 0, queue::dropped_message(n));
+                        if s.is_empty() {
+                            return;
                         }
-                    }
-                    return;
+                        log.output(LEVEL::Warn, "tklog", s.as_str())
+                    };
+                    rt.block_on(out.write());
+                },
+                || rt.block_on(write_due(global().file_handlers())),
+            );
+        })
+    }
+
+    /// Resolves where a formatted record goes: the module option, then the level option,
+    /// then the default file and console. Only the returned `Output` does any I/O.
+    fn output(&mut self, level: LEVEL, module: &str, message: &str) -> Output {
+        let msg = match &self.attrfmt.bodyfmt {
+            Some(f) => f(level, message.to_string()),
+            None => message.to_string(),
+        };
+        let mut route = None;
+        if self.modmap.len() > 0 {
+            if let Some((lo, filename)) = self.modmap.get(module) {
+                if !filename.is_empty() || !lo.async_sinks.is_empty() {
+                    route = Some((lo.console, filename.clone(), lo.async_sinks.clone()));
                 }
             }
         }
-
-        if let Some(levels) = &self.levels {
-            if let Some(lp) = &levels[level as usize - 1] {
-                let (lo, filename) = lp;
-                let mut is_level_console = self.fmthandle.get_console();
-                let mut is_consolefmt = false;
-                if let Some(cs) = lo.console {
-                    is_level_console = cs;
-                    if is_level_console && console.is_empty() {
-                        if let Some(f) = &self.attrfmt.console_bodyfmt {
-                            is_consolefmt = true;
-                            console = f(level, message.to_string());
-                        }
-                    }
-                }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self
-                            .filehandle
-                            .1
-                            .async_print(
-                                is_level_console,
-                                if is_level_console {
-                                    if is_consolefmt {
-                                        console.as_str()
-                                    } else {
-                                        ""
-                                    }
-                                } else {
-                                    ""
-                                },
-                                if is_bodyfmt { msg.as_str() } else { message },
-                            )
-                            .await;
-                    } else {
-                        if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm
-                                .async_print(
-                                    is_level_console,
-                                    if is_level_console {
-                                        if is_consolefmt {
-                                            console.as_str()
-                                        } else {
-                                            ""
-                                        }
-                                    } else {
-                                        ""
-                                    },
-                                    if is_bodyfmt { msg.as_str() } else { message },
-                                )
-                                .await;
-                        }
+        if route.is_none() {
+            if let Some(levels) = &self.levels {
+                if let Some((lo, filename)) = &levels[level as usize - 1] {
+                    if !filename.is_empty() || !lo.async_sinks.is_empty() {
+                        route = Some((lo.console, filename.clone(), lo.async_sinks.clone()));
                     }
-                    return;
                 }
             }
         }
-
-        let is_console = self.fmthandle.get_console();
-        let mut is_consolefmt = false;
-        if is_console && console.is_empty() {
-            if let Some(f) = &self.attrfmt.console_bodyfmt {
-                is_consolefmt = true;
-                console = f(level, message.to_string());
-            }
-        }
-        let _ = self
-            .filehandle
-            .1
-            .async_print(
-                is_console,
-                if is_console {
-                    if is_consolefmt {
-                        console.as_str()
-                    } else {
-                        ""
-                    }
+        let default_console = self.fmthandle.get_console();
+        let (is_console, is_consolefmt, handler, sinks, file) = match route {
+            Some((console, filename, sinks)) => {
+                // an option without a file of its own writes to the default one
+                let (handler, filename) = if filename.is_empty() || filename == self.filehandle.0 {
+                    (Some(self.filehandle.1.clone()), self.filehandle.0.clone())
                 } else {
-                    ""
-                },
-                if is_bodyfmt { msg.as_str() } else { message },
-            )
-            .await;
+                    (self.fmap.get(&filename).cloned(), filename)
+                };
+                let is_console = console.unwrap_or(default_console) && handler.is_some();
+                (is_console, console == Some(true), handler, sinks, filename)
+            }
+            None => (default_console, default_console, Some(self.filehandle.1.clone()), Vec::new(), self.filehandle.0.clone()),
+        };
+        let console = match &self.attrfmt.console_bodyfmt {
+            Some(f) if is_console && is_consolefmt => f(level, message.to_string()),
+            _ => String::new(),
+        };
+        Output { level, handler, is_console, console, message: msg, sinks, file, errors: self.errors.clone() }
+    }
+
+    pub async fn print(&mut self, level: LEVEL, module: &str, message: &str) {
+        self.output(level, module, message).write().await;
     }
 
     pub async fn safeprint(&mut self, level: LEVEL, module: &str, message: &str) {
+        let out = self.output(level, module, message);
         let _mutex_guard = self.mutex.lock().await;
+        out.write().await;
+    }
+
+    pub fn log(&self, level: LEVEL, module: String, message: String) {
+        self.queue.push(level, module, message);
+    }
 
-        let mut console = String::new();
-        let mut msg = String::new();
-        let mut is_bodyfmt = false;
+    /// Bounds the `PRINTMODE::DELAY` queue to `capacity` records (0 means unbounded)
+    /// and sets what happens to records arriving while it is full.
+    /// Records already queued are written before this returns.
+    pub async fn set_queue(&mut self, capacity: usize, overflow: OVERFLOW) -> &mut Self {
+        let old = self.replace_queue(capacity, overflow);
+        wait_writer(&old, Message::Shutdown).await;
+        self
+    }
 
-        if let Some(f) = &self.attrfmt.bodyfmt {
-            is_bodyfmt = true;
-            msg = f(level, message.to_string());
-        }
+    fn replace_queue(&mut self, capacity: usize, overflow: OVERFLOW) -> Queue {
+        let queue = Self::spawn_queue(capacity, overflow);
+        std::mem::replace(&mut self.queue, queue)
+    }
 
-        if self.modmap.len() > 0 {
-            if let Some(mm) = self.modmap.get(module) {
-                let (lo, filename) = mm;
-                let mut is_mod_console = self.fmthandle.get_console();
-                let mut is_consolefmt = false;
-                if let Some(cs) = lo.console {
-                    is_mod_console = cs;
-                    if is_mod_console {
-                        if let Some(f) = &self.attrfmt.console_bodyfmt {
-                            is_consolefmt = true;
-                            console = f(level, message.to_string());
-                        }
-                    }
-                }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self
-                            .filehandle
-                            .1
-                            .async_print(
-                                is_mod_console,
-                                if is_mod_console {
-                                    if is_consolefmt {
-                                        console.as_str()
-                                    } else {
-                                        ""
-                                    }
-                                } else {
-                                    ""
-                                },
-                                if is_bodyfmt { msg.as_str() } else { message },
-                            )
-                            .await;
-                    } else {
-                        if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm
-                                .async_print(
-                                    is_mod_console,
-                                    if is_mod_console {
-                                        if is_consolefmt {
-                                            console.as_str()
-                                        } else {
-                                            ""
-                                        }
-                                    } else {
-                                        ""
-                                    },
-                                    if is_bodyfmt { msg.as_str() } else { message },
-                                )
-                                .await;
-                        }
-                    }
-                    return;
-                }
-            }
-        }
+    /// The number of records discarded by the overflow policy since the queue was set.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
 
+    /// Calls `handler` with each error the logger has no caller to return to: files it could not
+    /// open, records it could not write, and failed rotations, compressions and retention runs.
+    /// It runs on the writing task, so it should return quickly and must not log through this logger.
+    pub fn set_error_handler(&mut self, handler: impl Fn(&LogError) + Send + Sync + 'static) -> &mut Self {
+        self.errors.set_handler(Arc::new(handler));
+        self
+    }
+
+    /// The errors met since the logger was created, by kind.
+    pub fn errors(&self) -> ErrorStats {
+        self.errors.stats()
+    }
+
+    /// Waits until every record queued in `PRINTMODE::DELAY` has been written,
+    /// then flushes the console, syncs the log files and flushes the sinks.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush).await;
+        flush_all(self.flush_targets()).await
+    }
+
+    /// Drains the queue like `flush`, then stops the background writer.
+    /// The logger switches to `PRINTMODE::PUNCTUAL`, so later records are still written;
+    /// records arriving through the `log` crate after shutdown are discarded.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.mode = PRINTMODE::PUNCTUAL;
+        wait_writer(&self.queue, Message::Shutdown).await;
+        flush_all(self.flush_targets()).await
+    }
+
+    /// Writes the records queued in `PRINTMODE::DELAY`, then reopens every log file at its
+    /// configured path without renaming it. Call it after logrotate or another tool has moved the files away.
+    pub async fn reopen(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush).await;
+        reopen_all(self.file_handlers()).await
+    }
+
+    /// Writes the records queued in `PRINTMODE::DELAY`, then rotates every log file at once,
+    /// renaming, compressing and pruning the backups as a scheduled rotation would.
+    pub async fn rotate_now(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush).await;
+        rotate_all(self.file_handlers()).await
+    }
+
+    fn file_handlers(&self) -> Vec<SharedHandler> {
+        let mut handlers = vec![self.filehandle.1.clone()];
+        handlers.extend(self.fmap.values().cloned());
+        handlers
+    }
+
+    fn flush_targets(&mut self) -> (Vec<SharedHandler>, Vec<AsyncSinkRef>) {
+        let handlers = self.file_handlers();
+        let mut sinks = Vec::new();
+        for (lo, _) in self.modmap.values() {
+            sinks.extend(lo.async_sinks.iter().cloned());
+        }
         if let Some(levels) = &self.levels {
-            if let Some(lp) = &levels[level as usize - 1] {
-                let (lo, filename) = lp;
-                let mut is_level_console = self.fmthandle.get_console();
-                let mut is_consolefmt = false;
-                if let Some(cs) = lo.console {
-                    is_level_console = cs;
-                    if is_level_console && console.is_empty() {
-                        if let Some(f) = &self.attrfmt.console_bodyfmt {
-                            is_consolefmt = true;
-                            console = f(level, message.to_string());
-                        }
-                    }
-                }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self
-                            .filehandle
-                            .1
-                            .async_print(
-                                is_level_console,
-                                if is_level_console {
-                                    if is_consolefmt {
-                                        console.as_str()
-                                    } else {
-                                        ""
-                                    }
-                                } else {
-                                    ""
-                                },
-                                if is_bodyfmt { msg.as_str() } else { message },
-                            )
-                            .await;
-                    } else {
-                        if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm
-                                .async_print(
-                                    is_level_console,
-                                    if is_level_console {
-                                        if is_consolefmt {
-                                            console.as_str()
-                                        } else {
-                                            ""
-                                        }
-                                    } else {
-                                        ""
-                                    },
-                                    if is_bodyfmt { msg.as_str() } else { message },
-                                )
-                                .await;
-                        }
-                    }
-                    return;
-                }
+            for (lo, _) in levels.iter().flatten() {
+                sinks.extend(lo.async_sinks.iter().cloned());
             }
         }
+        (handlers, sinks)
+    }
 
-        let is_console = self.fmthandle.get_console();
-        let mut is_consolefmt = false;
-        if is_console && console.is_empty() {
-            if let Some(f) = &self.attrfmt.console_bodyfmt {
-                is_consolefmt = true;
-                console = f(level, message.to_string());
-            }
+    // Formats a record for `submit`, returning the module it is routed by and the formatted line.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare(&mut self, target: &str, module: &str, level: LEVEL, filename: &str, line: u32, args: Vec<String>, fields: Vec<(String, FieldValue)>) -> Option<(String, String)> {
+        let route = self.route_module(target, module);
+        let (filename, line) = if self.is_file_line(level, route) { (filename, line) } else { ("", 0) };
+        let message = args.join(self.separator.as_str());
+        let s = self.fmt_record(target, module, level, filename, line, message, fields);
+        if s.is_empty() {
+            return None;
         }
-        let _ = self
-            .filehandle
-            .1
-            .async_print(
-                is_console,
-                if is_console {
-                    if is_consolefmt {
-                        console.as_str()
-                    } else {
-                        ""
-                    }
-                } else {
-                    ""
-                },
-                if is_bodyfmt { msg.as_str() } else { message },
-            )
-            .await;
+        Some((route.to_string(), s))
     }
 
-    pub fn log(&self, level: LEVEL, module: String, message: String) {
-        self.sender.send((level, module, message)).expect("send error");
+    /// Returns `target` when a module option is registered for it, otherwise `module`.
+    pub fn route_module<'a>(&mut self, target: &'a str, module: &'a str) -> &'a str {
+        if !target.is_empty() && target != module && self.modmap.len() > 0 && self.modmap.get(target).is_some() {
+            return target;
+        }
+        module
     }
 
     pub fn get_level(&mut self, module: &str) -> LEVEL {
@@ -430,17 +331,27 @@ impl Logger {
     }
 
     pub fn fmt(&mut self, module: &str, level: LEVEL, filename: &str, line: u32, message: String) -> String {
-        if self.custom_handler.is_some() {
-            if let Some(ch) = &self.custom_handler {
-                if !ch(&LogContext { level: level, filename: filename.to_string(), line: line, log_body: message.clone(), modname: module.to_string() }) {
-                    return String::new();
-                }
+        self.fmt_fields(module, level, filename, line, message, Vec::new())
+    }
+
+    /// Same as `fmt`, with the `key = value` fields given to the logging macros.
+    pub fn fmt_fields(&mut self, module: &str, level: LEVEL, filename: &str, line: u32, message: String, fields: Vec<(String, FieldValue)>) -> String {
+        self.fmt_record(module, module, level, filename, line, message, fields)
+    }
+
+    /// Formats a record that carries a `log` target besides its module path.
+    /// Module and level options are looked up by the key returned from `route_module`.
+    pub fn fmt_record(&mut self, target: &str, module: &str, level: LEVEL, filename: &str, line: u32, message: String, fields: Vec<(String, FieldValue)>) -> String {
+        if let Some(ch) = &self.custom_handler {
+            if !ch(&LogContext { level: level, filename: filename.to_string(), line: line, log_body: message.clone(), modname: module.to_string(), fields: fields.clone(), target: target.to_string() }) {
+                return String::new();
             }
         }
+        let route = self.route_module(target, module);
         let mut fmat = self.fmthandle.get_format();
         let mut formatter = self.fmthandle.get_formatter();
-        if module != "" && self.modmap.len() > 0 {
-            if let Some(mm) = self.modmap.get(module) {
+        if route != "" && self.modmap.len() > 0 {
+            if let Some(mm) = self.modmap.get(route) {
                 let (lo, _) = mm;
                 if let Some(v) = lo.format {
                     fmat = v;
@@ -462,7 +373,8 @@ impl Logger {
                 }
             }
         }
-        log_fmt(self.attrfmt.levelfmt.as_ref(), self.attrfmt.timefmt.as_ref(), fmat, formatter, level, filename, line, message.as_str())
+        let rec = FmtRecord { level, module, filename, line, msg: message.as_str(), fields: &fields };
+        log_fmt(self.attrfmt.levelfmt.as_ref(), self.attrfmt.timefmt.as_ref(), fmat, formatter, &rec)
     }
 
     pub fn set_printmode(&mut self, mode: PRINTMODE) -> &mut Self {
@@ -475,6 +387,26 @@ impl Logger {
         self
     }
 
+    /// Sets the level of `module` and its submodules.
+    /// An option already matching the module, such as its log file, is kept.
+    pub fn set_mod_level(&mut self, module: &str, level: LEVEL) -> &mut Self {
+        let (mut lo, filename) = self.modmap.get(module).cloned().unwrap_or_default();
+        lo.level = Some(level);
+        self.modmap.insert(module, (lo, filename));
+        self
+    }
+
+    /// Applies `RUST_LOG` style directives, see [`crate::parse_directives`].
+    pub fn set_directives(&mut self, directives: &[Directive]) -> &mut Self {
+        for d in directives {
+            match &d.module {
+                Some(module) => self.set_mod_level(module, d.level),
+                None => self.set_level(d.level),
+            };
+        }
+        self
+    }
+
     pub fn set_console(&mut self, console: bool) -> &mut Self {
         self.fmthandle.set_console(console);
         self
@@ -492,31 +424,74 @@ impl Logger {
         self
     }
 
+    /// When the file cannot be opened, the error goes to the error handler and records keep going
+    /// where they went before; the `try_` variant returns the error instead.
     pub async fn set_cutmode_by_size(&mut self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> &mut Self {
-        let fsm = FileOptionType::new(crate::CUTMODE::SIZE, MODE::DAY, filename, maxsize, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(fsm)).await;
-        self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_async_file_handler(fh.unwrap());
+        if let Err(e) = self.try_set_cutmode_by_size(filename, maxsize, maxbackups, compress).await {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
         self
     }
 
+    pub async fn try_set_cutmode_by_size(&mut self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
+        let fsm = FileOptionType::new(crate::CUTMODE::SIZE, MODE::DAY, filename, maxsize, maxbackups, compress);
+        let fh = FileHandler::with_errors(Box::new(fsm), self.errors.clone()).await?;
+        install(self.set_filename(filename), Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
     pub async fn set_cutmode_by_time(&mut self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> &mut Self {
-        let ftm = FileOptionType::new(crate::CUTMODE::TIME, mode, filename, 0, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(ftm)).await;
-        self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_async_file_handler(fh.unwrap());
+        if let Err(e) = self.try_set_cutmode_by_time(filename, mode, maxbackups, compress).await {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
         self
     }
 
+    pub async fn try_set_cutmode_by_time(&mut self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
+        let ftm = FileOptionType::new(crate::CUTMODE::TIME, mode, filename, 0, maxbackups, compress);
+        let fh = FileHandler::with_errors(Box::new(ftm), self.errors.clone()).await?;
+        install(self.set_filename(filename), Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
     pub async fn set_cutmode_by_mixed(&mut self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> &mut Self {
+        if let Err(e) = self.try_set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress).await {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
+        self
+    }
+
+    pub async fn try_set_cutmode_by_mixed(&mut self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
         let ftm = FileOptionType::new(crate::CUTMODE::MIXED, mode, filename, maxsize, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(ftm)).await;
+        let fh = FileHandler::with_errors(Box::new(ftm), self.errors.clone()).await?;
+        install(self.set_filename(filename), Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
+    fn set_filename(&mut self, filename: &str) -> SharedHandler {
         self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_async_file_handler(fh.unwrap());
+        self.filehandle.1.clone()
+    }
+
+    pub async fn set_option(&mut self, option: impl Into<SinkOption>) -> &mut Self {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = open(option.fileoption.take(), &self.errors).await;
+        let handler = self.apply_option(option, fh.as_ref());
+        install(handler, sinks, fh).await;
         self
     }
 
-    pub async fn set_option(&mut self, option: LogOption) -> &mut Self {
+    /// Like `set_option`, but returns the error when the log file cannot be opened, leaving the logger unchanged.
+    pub async fn try_set_option(&mut self, option: impl Into<SinkOption>) -> io::Result<&mut Self> {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = try_open(option.fileoption.take(), &self.errors).await?;
+        let handler = self.apply_option(option, fh.as_ref());
+        install(handler, sinks, fh).await;
+        Ok(self)
+    }
+
+    // The synchronous part of `set_option`; the sinks and file are installed by `install`.
+    fn apply_option(&mut self, option: LogOption, fh: Option<&FileHandler>) -> SharedHandler {
         if let Some(v) = option.console {
             self.fmthandle.set_console(v);
         }
@@ -529,34 +504,34 @@ impl Logger {
         if let Some(v) = option.level {
             self.fmthandle.set_level(v);
         }
-        if let Some(v) = option.fileoption {
-            match FileHandler::new(v).await {
-                Ok(f) => {
-                    self.filehandle.0 = f.get_file_name();
-                    self.filehandle.1.set_async_file_handler(f);
-                }
-                Err(_) => {}
-            }
+        if let Some(f) = fh {
+            self.filehandle.0 = f.get_file_name();
         }
-        self
+        self.filehandle.1.clone()
     }
 
-    pub async fn set_mod_option(&mut self, module: &str, option: LogOption) -> &mut Self {
+    pub async fn set_mod_option(&mut self, module: &str, option: impl Into<SinkOption>) -> &mut Self {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = open(option.fileoption.take(), &self.errors).await;
+        self.apply_mod_option(module, option, sinks, fh)
+    }
+
+    fn add_file(&mut self, fh: Option<FileHandler>) -> String {
         let mut filename = "".to_string();
-        if let Some(v) = option.fileoption {
-            match FileHandler::new(v).await {
-                Ok(f) => {
-                    filename = f.get_file_name();
-                    if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
-                        let mut fhandler = FHandler::new();
-                        fhandler.set_async_file_handler(f);
-                        self.fmap.insert(filename.clone(), fhandler);
-                    }
-                }
-                Err(_) => {}
+        if let Some(f) = fh {
+            filename = f.get_file_name();
+            if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
+                let mut fhandler = FHandler::new();
+                fhandler.set_async_file_handler(f);
+                self.fmap.insert(filename.clone(), Arc::new(tokio::sync::Mutex::new(fhandler)));
             }
         }
-        self.modmap.insert(module, (LogOptionConst { level: option.level, format: option.format, formatter: option.formatter, console: option.console }, filename.clone()));
+        filename
+    }
+
+    fn apply_mod_option(&mut self, module: &str, option: LogOption, async_sinks: Vec<AsyncSinkRef>, fh: Option<FileHandler>) -> &mut Self {
+        let filename = self.add_file(fh);
+        self.modmap.insert(module, (LogOptionConst { level: option.level, format: option.format, formatter: option.formatter, console: option.console, sinks: Vec::new(), async_sinks }, filename));
         self
     }
 
@@ -565,21 +540,13 @@ impl Logger {
     }
 
     pub async fn set_level_option(&mut self, level: LEVEL, option: &dyn OptionTrait) -> &mut Self {
-        let mut filename = "".to_string();
-        if let Some(v) = option.get_fileoption() {
-            match FileHandler::new(v).await {
-                Ok(f) => {
-                    filename = f.get_file_name();
-                    if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
-                        let mut fhandler = FHandler::new();
-                        fhandler.set_async_file_handler(f);
-                        self.fmap.insert(filename.clone(), fhandler);
-                    }
-                }
-                Err(_) => {}
-            }
-        }
-        let lo = LogOption { level: None, format: option.get_format(), formatter: option.get_formatter(), console: option.get_console(), fileoption: option.get_fileoption() };
+        let fh = open(option.get_fileoption(), &self.errors).await;
+        self.apply_level_option(level, option, fh)
+    }
+
+    fn apply_level_option(&mut self, level: LEVEL, option: &dyn OptionTrait, fh: Option<FileHandler>) -> &mut Self {
+        let filename = self.add_file(fh);
+        let lo = LogOptionConst { level: None, format: option.get_format(), formatter: option.get_formatter(), console: option.get_console(), sinks: Vec::new(), async_sinks: option.get_async_sinks() };
 
         if self.levels.is_none() {
             self.levels = Some(std::array::from_fn(|_| None));
@@ -597,6 +564,65 @@ impl Logger {
         self
     }
 
+    /// Loads a `.toml` or `.json` configuration file, see [`crate::config`].
+    /// An invalid file returns an error and leaves the logger unchanged.
+    pub async fn load_config(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, ConfigError> {
+        let config = read_config(path.as_ref()).await?;
+        self.apply_config(&config).await?;
+        self.config_path = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub async fn apply_config(&mut self, config: &Config) -> Result<&mut Self, ConfigError> {
+        let opened = OpenedSettings::open(config.settings()?, &self.errors).await;
+        let (handler, fh) = self.apply_settings(opened, false);
+        install(handler, Vec::new(), fh).await;
+        Ok(self)
+    }
+
+    /// Reads the file given to `load_config` again and swaps in its configuration, see `reload_config`.
+    pub async fn reload(&mut self) -> Result<&mut Self, ConfigError> {
+        let config = read_config(&self.config_path()?).await?;
+        self.reload_config(&config).await
+    }
+
+    /// Replaces the configuration with `config`: levels, formats, module options and level options
+    /// missing from it return to their defaults instead of keeping their current values.
+    /// Queued records, the custom handler, attribute formats and sinks of the main file are kept.
+    pub async fn reload_config(&mut self, config: &Config) -> Result<&mut Self, ConfigError> {
+        let opened = OpenedSettings::open(config.settings()?, &self.errors).await;
+        let (handler, fh) = self.apply_settings(opened, true);
+        install(handler, Vec::new(), fh).await;
+        Ok(self)
+    }
+
+    fn config_path(&self) -> Result<PathBuf, ConfigError> {
+        self.config_path.clone().ok_or_else(|| ConfigError::Invalid("no config file has been loaded".to_string()))
+    }
+
+    // Files are opened beforehand, so a whole config is applied under one lock of the global logger.
+    fn apply_settings(&mut self, mut opened: OpenedSettings, reset: bool) -> (SharedHandler, Option<FileHandler>) {
+        if reset {
+            self.reset_options();
+        }
+        let settings = &mut opened.settings;
+        if let Some(mode) = settings.printmode.take() {
+            self.set_printmode(mode);
+        }
+        if let Some(separator) = &settings.separator {
+            self.set_separator(separator);
+        }
+        let handler = self.apply_option(settings.option.take(), opened.file.as_ref());
+        for ((module, option), fh) in settings.modules.drain(..).zip(opened.modules) {
+            self.apply_mod_option(&module, option, Vec::new(), fh);
+        }
+        for ((level, option), fh) in settings.levels.drain(..).zip(opened.levels) {
+            self.apply_level_option(level, &option, fh);
+        }
+        self.set_directives(&settings.directives);
+        (handler, opened.file)
+    }
+
     pub fn get_separator(&self) -> String {
         self.separator.clone()
     }
@@ -616,102 +642,228 @@ impl Log {
         Log {}
     }
     pub fn set_printmode(&self, mode: PRINTMODE) -> &Self {
-        unsafe {
-            asynclog.set_printmode(mode);
-        }
+        global().set_printmode(mode);
         self
     }
 
     pub fn set_level(&self, level: LEVEL) -> &Self {
-        unsafe {
-            asynclog.set_level(level);
-        }
+        global().set_level(level);
+        self
+    }
+
+    pub fn set_mod_level(&self, module: &str, level: LEVEL) -> &Self {
+        global().set_mod_level(module, level);
+        self
+    }
+
+    pub fn set_directives(&self, directives: &[Directive]) -> &Self {
+        global().set_directives(directives);
         self
     }
 
     pub fn set_console(&self, console: bool) -> &Self {
-        unsafe {
-            asynclog.set_console(console);
-        }
+        global().set_console(console);
         self
     }
 
     /**Format::LevelFlag | Format::Date | Format::Time | Format::ShortFileName; */
     pub fn set_format(&self, format: u8) -> &Self {
-        unsafe {
-            asynclog.set_format(format);
-        }
+        global().set_format(format);
         self
     }
 
     /** default: "{level}{time} {file}:{message}\n" */
     pub fn set_formatter(&self, formatter: &str) -> &Self {
-        unsafe {
-            asynclog.set_formatter(formatter);
-        }
+        global().set_formatter(formatter);
         self
     }
 
     pub async fn set_cutmode_by_size(&self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            asynclog.set_cutmode_by_size(filename, maxsize, maxbackups, compress).await;
+        if let Err(e) = self.try_set_cutmode_by_size(filename, maxsize, maxbackups, compress).await {
+            global_errors().report(FAILURE::OPEN, filename, e);
         }
         self
     }
 
+    pub async fn try_set_cutmode_by_size(&self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        let fsm = FileOptionType::new(crate::CUTMODE::SIZE, MODE::DAY, filename, maxsize, maxbackups, compress);
+        let fh = FileHandler::with_errors(Box::new(fsm), global_errors()).await?;
+        let handler = global().set_filename(filename);
+        install(handler, Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
     pub async fn set_cutmode_by_time(&self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            asynclog.set_cutmode_by_time(filename, mode, maxbackups, compress).await;
+        if let Err(e) = self.try_set_cutmode_by_time(filename, mode, maxbackups, compress).await {
+            global_errors().report(FAILURE::OPEN, filename, e);
         }
         self
     }
 
+    pub async fn try_set_cutmode_by_time(&self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        let ftm = FileOptionType::new(crate::CUTMODE::TIME, mode, filename, 0, maxbackups, compress);
+        let fh = FileHandler::with_errors(Box::new(ftm), global_errors()).await?;
+        let handler = global().set_filename(filename);
+        install(handler, Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
     pub async fn set_cutmode_by_mixed(&self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            asynclog.set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress).await;
+        if let Err(e) = self.try_set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress).await {
+            global_errors().report(FAILURE::OPEN, filename, e);
         }
         self
     }
 
+    pub async fn try_set_cutmode_by_mixed(&self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        let ftm = FileOptionType::new(crate::CUTMODE::MIXED, mode, filename, maxsize, maxbackups, compress);
+        let fh = FileHandler::with_errors(Box::new(ftm), global_errors()).await?;
+        let handler = global().set_filename(filename);
+        install(handler, Vec::new(), Some(fh)).await;
+        Ok(self)
+    }
+
     pub fn set_custom_handler(&self, handler: fn(&LogContext) -> bool) -> &Self {
-        unsafe { asynclog.set_custom_handler(handler) }
+        global().set_custom_handler(handler);
         self
     }
 
-    fn is_file_line(&self, level: LEVEL, module: &str) -> bool {
-        unsafe {
-            return asynclog.is_file_line(level, module);
-        }
+    pub async fn set_option(&self, option: impl Into<SinkOption>) -> &Self {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = open(option.fileoption.take(), &global_errors()).await;
+        let handler = global().apply_option(option, fh.as_ref());
+        install(handler, sinks, fh).await;
+        self
     }
 
-    pub async fn set_option(&self, option: LogOption) -> &Self {
-        unsafe {
-            asynclog.set_option(option).await;
-        }
-        self
+    pub async fn try_set_option(&self, option: impl Into<SinkOption>) -> io::Result<&Self> {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = try_open(option.fileoption.take(), &global_errors()).await?;
+        let handler = global().apply_option(option, fh.as_ref());
+        install(handler, sinks, fh).await;
+        Ok(self)
     }
 
-    pub async fn set_mod_option(&self, module: &str, option: LogOption) -> &Self {
-        unsafe {
-            asynclog.set_mod_option(module, option).await;
-        }
+    pub async fn set_mod_option(&self, module: &str, option: impl Into<SinkOption>) -> &Self {
+        let (mut option, _, sinks) = option.into().into_parts();
+        let fh = open(option.fileoption.take(), &global_errors()).await;
+        global().apply_mod_option(module, option, sinks, fh);
         self
     }
 
     pub async fn set_level_option(&self, level: LEVEL, option: impl OptionTrait) -> &Self {
-        unsafe {
-            asynclog.set_level_option(level, &option).await;
-        }
+        let fh = open(option.get_fileoption(), &global_errors()).await;
+        global().apply_level_option(level, &option, fh);
         self
     }
 
     pub fn set_separator(&self, separator: &str) -> &Self {
-        unsafe {
-            asynclog.set_separator(separator);
-        };
+        global().set_separator(separator);
+        self
+    }
+
+    pub async fn load_config(&self, path: impl AsRef<Path>) -> Result<&Self, ConfigError> {
+        let config = read_config(path.as_ref()).await?;
+        self.apply_config(&config).await?;
+        global().config_path = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub async fn apply_config(&self, config: &Config) -> Result<&Self, ConfigError> {
+        let opened = OpenedSettings::open(config.settings()?, &global_errors()).await;
+        let (handler, fh) = global().apply_settings(opened, false);
+        install(handler, Vec::new(), fh).await;
+        Ok(self)
+    }
+
+    pub async fn reload(&self) -> Result<&Self, ConfigError> {
+        let path = global().config_path()?;
+        let config = read_config(&path).await?;
+        self.reload_config(&config).await
+    }
+
+    pub async fn reload_config(&self, config: &Config) -> Result<&Self, ConfigError> {
+        let opened = OpenedSettings::open(config.settings()?, &global_errors()).await;
+        let (handler, fh) = global().apply_settings(opened, true);
+        install(handler, Vec::new(), fh).await;
+        Ok(self)
+    }
+
+    /// Loads `path`, then polls it every `interval` and reloads it when it changes.
+    /// A reload that fails is logged and the previous configuration stays in place.
+    /// Watching another file, or the same one again, stops the previous watch.
+    pub async fn watch_config(&self, path: impl AsRef<Path>, interval: Duration) -> Result<&Self, ConfigError> {
+        self.load_config(path.as_ref()).await?;
+        *WATCHER.lock().unwrap_or_else(|e| e.into_inner()) = Some(config::watch(path, interval, reload_global));
+        Ok(self)
+    }
+
+    /// Reloads the file given to `load_config` whenever the process receives SIGHUP.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(&self) -> io::Result<&Self> {
+        config::on_sighup(reload_global)?;
+        Ok(self)
+    }
+
+    pub async fn flush(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush).await;
+        let targets = global().flush_targets();
+        flush_all(targets).await
+    }
+
+    pub async fn reopen(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush).await;
+        let handlers = global().file_handlers();
+        reopen_all(handlers).await
+    }
+
+    pub async fn rotate_now(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush).await;
+        let handlers = global().file_handlers();
+        rotate_all(handlers).await
+    }
+
+    /// Reopens the log files whenever the process receives `signal`, e.g. from a logrotate
+    /// `postrotate` script. Pass `SignalKind::hangup()` only when `reload_on_sighup` is not used.
+    #[cfg(unix)]
+    pub fn reopen_on_signal(&self, signal: SignalKind) -> io::Result<&Self> {
+        config::on_signal(signal, reopen_global)?;
+        Ok(self)
+    }
+
+    pub async fn set_queue(&self, capacity: usize, overflow: OVERFLOW) -> &Self {
+        let old = global().replace_queue(capacity, overflow);
+        wait_writer(&old, Message::Shutdown).await;
+        self
+    }
+
+    pub fn dropped(&self) -> u64 {
+        global().dropped()
+    }
+
+    pub fn set_error_handler(&self, handler: impl Fn(&LogError) + Send + Sync + 'static) -> &Self {
+        global().set_error_handler(handler);
         self
     }
 
+    pub fn errors(&self) -> ErrorStats {
+        global().errors()
+    }
+
+    pub async fn shutdown(&self) -> io::Result<()> {
+        let queue = {
+            let mut log = global();
+            log.mode = PRINTMODE::PUNCTUAL;
+            log.queue.clone()
+        };
+        wait_writer(&queue, Message::Shutdown).await;
+        let targets = global().flush_targets();
+        flush_all(targets).await
+    }
+
     pub fn uselog(&self) -> &Self {
         let _ = log::set_logger(&TKLOG2ASYNC_LOG);
         log::set_max_level(log::LevelFilter::Trace);
@@ -722,9 +874,7 @@ impl Log {
     where
         F: FnMut(&mut AttrFormat) + Send + Sync + 'static,
     {
-        unsafe {
-            asynclog.set_attr_format(f);
-        }
+        global().set_attr_format(f);
     }
 }
 
@@ -732,33 +882,258 @@ impl log::Log for Log {
     fn enabled(&self, _: &log::Metadata) -> bool {
         return true;
     }
+    // `log` calls cannot await, so records from the `log` crate always go through the writer queue.
     fn log(&self, record: &log::Record) {
         let level = l2tk(record.level());
-        let mut module = "";
-        if let Some(m) = record.module_path() {
-            module = m;
-            unsafe {
-                if asynclog.get_level(module) > level {
-                    return;
+        let module = record.module_path().unwrap_or("");
+        let target = record.target();
+        {
+            let mut log = global();
+            let route = log.route_module(target, module);
+            if log.get_level(route) > level {
+                return;
+            }
+        }
+        let args = vec![arguments_to_string(record.args())];
+        let mut log = global();
+        if let Some((route, s)) = log.prepare(target, module, level, record.file().unwrap_or(""), record.line().unwrap_or(0), args, record_fields(record)) {
+            let queue = log.queue.clone();
+            drop(log);
+            queue.push(level, route, s);
+        }
+    }
+    fn flush(&self) {
+        // A current-thread runtime cannot be blocked on from inside itself; callers there should await `ASYNC_LOG.flush()`.
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread {
+                    let _ = tokio::task::block_in_place(|| handle.block_on(Log::flush(self)));
+                }
+            }
+            Err(_) => {
+                if let Ok(rt) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    let _ = rt.block_on(Log::flush(self));
                 }
             }
         }
+    }
+}
+
+/// The I/O half of printing a record, built by `Logger::output` so it can run without the logger lock.
+struct Output {
+    level: LEVEL,
+    handler: Option<SharedHandler>,
+    is_console: bool,
+    console: String,
+    message: String,
+    sinks: Vec<AsyncSinkRef>,
+    file: String,
+    errors: Reporter,
+}
+
+impl Output {
+    async fn write(self) {
+        if let Some(h) = &self.handler {
+            let r = h.lock().await.async_print(self.level, self.is_console, self.console.as_str(), self.message.as_str()).await;
+            if let Err(e) = r {
+                self.errors.report(FAILURE::WRITE, &self.file, e);
+            }
+        } else if self.is_console {
+            let _ = AsyncSink::write(&mut ConsoleSink::new(), self.level, if self.console.is_empty() { self.message.as_str() } else { self.console.as_str() }).await;
+        }
+        if !self.sinks.is_empty() {
+            if let Err(e) = async_write_sinks(&self.sinks, self.level, self.message.as_str()).await {
+                self.errors.report(FAILURE::WRITE, "", e);
+            }
+        }
+    }
+}
+
+/// The files of a `Settings`, opened before the logger lock is taken.
+struct OpenedSettings {
+    settings: Settings,
+    file: Option<FileHandler>,
+    modules: Vec<Option<FileHandler>>,
+    levels: Vec<Option<FileHandler>>,
+}
 
-        let args = record.args();
-        let mut file = "";
-        let mut line: u32 = 0;
-        if self.is_file_line(level, module) {
-            line = record.line().unwrap_or(0);
-            file = record.file().unwrap_or("");
+impl OpenedSettings {
+    async fn open(mut settings: Settings, errors: &Reporter) -> Self {
+        // before the file handlers below compute their first rotation
+        if let Some(timezone) = settings.timezone {
+            crate::set_timezone(timezone);
         }
-        unsafe {
-            let s = asynclog.fmt(module, level, file, line, arguments_to_string(args));
-            if !s.is_empty() {
-                asynclog.log(level, module.to_string(), s);
+        let file = open(settings.option.fileoption.take(), errors).await;
+        let mut modules = Vec::new();
+        for (_, option) in &mut settings.modules {
+            modules.push(open(option.fileoption.take(), errors).await);
+        }
+        let mut levels = Vec::new();
+        for (_, option) in &mut settings.levels {
+            levels.push(open(option.fileoption.take(), errors).await);
+        }
+        OpenedSettings { settings, file, modules, levels }
+    }
+}
+
+// Reloads and reopens triggered by a file change or a signal run on a runtime of their own,
+// since the runtime that set them up may be gone by then.
+fn reload_global() {
+    if let Ok(rt) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        rt.block_on(report_reload());
+    }
+}
+
+fn reopen_global() {
+    if let Ok(rt) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        let _ = rt.block_on(Log::reopen(&Log));
+    }
+}
+
+async fn report_reload() {
+    if let Err(e) = Log::reload(&Log).await {
+        let out = {
+            let mut log = global();
+            let s = log.fmt("tklog", LEVEL::Error, "", 0, format!("tklog failed to reload config: {}", e));
+            if s.is_empty() {
+                return;
             }
+            log.output(LEVEL::Error, "tklog", s.as_str())
+        };
+        out.write().await;
+    }
+}
+
+async fn read_config(path: &Path) -> Result<Config, ConfigError> {
+    let content = tokio::fs::read_to_string(path).await.map_err(|e| ConfigError::Io(path.display().to_string(), e))?;
+    Config::from_content(path, &content)
+}
+
+async fn try_open(fileoption: Option<Box<dyn FileOption>>, errors: &Reporter) -> io::Result<Option<FileHandler>> {
+    match fileoption {
+        Some(v) => Ok(Some(FileHandler::with_errors(v, errors.clone()).await?)),
+        None => Ok(None),
+    }
+}
+
+// Opens a log file, reporting the error when it cannot.
+async fn open(fileoption: Option<Box<dyn FileOption>>, errors: &Reporter) -> Option<FileHandler> {
+    let filename = fileoption.as_ref().map(|fo| fo.filename()).unwrap_or_default();
+    match try_open(fileoption, errors).await {
+        Ok(fh) => fh,
+        Err(e) => {
+            errors.report(FAILURE::OPEN, filename, e);
+            None
+        }
+    }
+}
+
+async fn install(handler: SharedHandler, sinks: Vec<AsyncSinkRef>, fh: Option<FileHandler>) {
+    let mut h = handler.lock().await;
+    for sink in sinks {
+        h.add_async_sink(sink);
+    }
+    if let Some(f) = fh {
+        h.set_async_file_handler(f);
+    }
+}
+
+async fn flush_all((handlers, sinks): (Vec<SharedHandler>, Vec<AsyncSinkRef>)) -> io::Result<()> {
+    let mut r = Ok(());
+    for h in handlers {
+        if let Err(e) = h.lock().await.async_flush().await {
+            r = Err(e);
+        }
+    }
+    if let Err(e) = async_flush_sinks(&sinks).await {
+        r = Err(e);
+    }
+    r
+}
+
+// Writes the file buffers whose flush interval has passed; returns how long until the next may be due.
+async fn write_due(handlers: Vec<SharedHandler>) -> Option<Duration> {
+    let mut next: Option<Duration> = None;
+    for h in handlers {
+        next = match (next, h.lock().await.async_write_due().await) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    next
+}
+
+async fn reopen_all(handlers: Vec<SharedHandler>) -> io::Result<()> {
+    let mut r = Ok(());
+    for h in handlers {
+        if let Err(e) = h.lock().await.async_reopen().await {
+            r = Err(e);
+        }
+    }
+    r
+}
+
+async fn rotate_all(handlers: Vec<SharedHandler>) -> io::Result<()> {
+    let mut r = Ok(());
+    for h in handlers {
+        if let Err(e) = h.lock().await.async_rotate_now().await {
+            r = Err(e);
         }
     }
-    fn flush(&self) {}
+    r
+}
+
+// The file `watch_config` polls for the global logger.
+static WATCHER: Mutex<Option<config::Watcher>> = Mutex::new(None);
+
+fn global() -> MutexGuard<'static, Logger> {
+    asynclog.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Taken on its own, so the logger lock is not held across the `.await` of opening a file.
+fn global_errors() -> Reporter {
+    global().errors.clone()
+}
+
+// Sends a flush or shutdown marker and waits until the writer reaches it.
+async fn wait_writer(queue: &Queue, marker: fn(Ack) -> Message) {
+    let (ack, done) = oneshot::channel();
+    if queue.control(marker(Box::new(move || {
+        let _ = ack.send(());
+    }))) {
+        let _ = done.await;
+    }
+}
+
+#[doc(hidden)]
+pub fn enabled(level: LEVEL, module: &str) -> bool {
+    global().get_level(module) <= level
+}
+
+/// Formats a record with the global logger, then queues it in `PRINTMODE::DELAY` or prints it.
+/// The logger lock is never held across an `.await`, so callers' futures stay `Send`.
+#[doc(hidden)]
+pub async fn submit(target: &str, module: &str, level: LEVEL, filename: &str, line: u32, args: Vec<String>, fields: Vec<(String, FieldValue)>) {
+    let out = {
+        let mut log = global();
+        let Some((route, s)) = log.prepare(target, module, level, filename, line, args, fields) else {
+            return;
+        };
+        if log.mode == PRINTMODE::DELAY {
+            let queue = log.queue.clone();
+            drop(log);
+            queue.push(level, route, s);
+            return;
+        }
+        log.output(level, route.as_str(), s.as_str())
+    };
+    out.write().await;
+}
+
+#[doc(hidden)]
+pub async fn print(level: LEVEL, module: &str, message: &str) {
+    let out = global().output(level, module, message);
+    out.write().await;
 }
 
 #[macro_export]
@@ -766,15 +1141,19 @@ macro_rules! async_log {
     ($level:expr,$module:expr,$msg:expr) => {
         let msg: &str = $msg;
         let module: &str = $module;
-        unsafe {
-            crate::tklog::asynclog.print($level, module, msg).await;
-        }
+        $crate::Async::print($level, module, msg).await;
     };
 }
 
 #[macro_export]
 macro_rules! async_trace {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Trace, $($arg),*);
     };
@@ -783,6 +1162,12 @@ macro_rules! async_trace {
 #[macro_export]
 macro_rules! async_debug {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Debug, $($arg),*);
     };
@@ -791,6 +1176,12 @@ macro_rules! async_debug {
 #[macro_export]
 macro_rules! async_info {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Info, $($arg),*);
     };
@@ -799,6 +1190,12 @@ macro_rules! async_info {
 #[macro_export]
 macro_rules! async_warn {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Warn, $($arg),*);
     };
@@ -807,6 +1204,12 @@ macro_rules! async_warn {
 #[macro_export]
 macro_rules! async_error {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Error, $($arg),*);
     };
@@ -815,6 +1218,12 @@ macro_rules! async_error {
 #[macro_export]
 macro_rules! async_fatal {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_log_common!($crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_log_common!($crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::async_log_common!($crate::LEVEL::Fatal, $($arg),*);
     };
@@ -822,31 +1231,19 @@ macro_rules! async_fatal {
 
 #[macro_export]
 macro_rules! async_log_common {
-    ($level:expr, $($arg:expr),*) => {
-        unsafe {
+    ($level:expr, [$($key:ident $(:$capture:tt)? = $value:expr),*], $($arg:expr),*) => {
+        {
+            let level: $crate::LEVEL = $level;
             let module = module_path!();
-            if $crate::tklog::asynclog.get_level(module) <= $level {
+            if $crate::Async::enabled(level, module) {
                 let formatted_args: Vec<String> = vec![$(format!("{}", $arg)),*];
-                let mut file = "";
-                let mut line = 0;
-                if $crate::tklog::asynclog.is_file_line($level,module) {
-                    file = file!();
-                    line = line!();
-                }
-                let msg: String = formatted_args.join($crate::tklog::asynclog.get_separator().as_str());
-                if  $crate::tklog::asynclog.mode==$crate::PRINTMODE::DELAY {
-                    let s = $crate::tklog::asynclog.fmt(module,$level, file, line, msg);
-                    if !s.is_empty(){
-                        $crate::tklog::asynclog.log($level,module.to_string(),s);
-                    }
-                }else {
-                    let s = $crate::tklog::asynclog.fmt(module,$level, file, line, msg);
-                    if !s.is_empty(){
-                        $crate::tklog::asynclog.safeprint($level,module,s.as_str()).await;
-                    }
-                }
+                let fields: Vec<(String, $crate::FieldValue)> = vec![$((stringify!($key).to_string(), $crate::field_value!($($capture)? $value))),*];
+                $crate::Async::submit(module, module, level, file!(), line!(), formatted_args, fields).await;
             }
         }
     };
+    ($level:expr, $($arg:expr),*) => {
+        $crate::async_log_common!($level, [], $($arg),*);
+    };
     () => {};
 }
@@ -20,37 +20,83 @@ use std::{
     ffi::OsStr,
     io::{Error, ErrorKind},
     path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use chrono::{DateTime, Local};
-use regex::Regex;
 use tokio::{
     fs::{self, File, OpenOptions},
     io::{self, AsyncWriteExt},
 };
 
-use crate::{async_gzip, getbackup_with_time, handle::FileOption, passtimemode, timesec, ErrCode, CUTMODE, MODE};
+use crate::{async_compress, async_update_link, compressed_exists, free_space, handle::FileOption, lock_file, log_dir, naming::BackupName, next_rotation, period, same_file, set_started_file, started_file, timesec, DiskGuard, Durability, ErrCode, Failover, Outage, Reporter, Retention, CODEC, CUTMODE, FAILURE, FALLBACK, LEVEL, MODE, STARTUP};
 
 pub struct FileHandler {
     filename: String, //Log file path
+    path: PathBuf,    //The file being written, which the log file path links to in link mode
+    link: bool,       //Whether the log file path is a symlink to the file being written
     max_size: u64,    //Maximum size for each log file to be saved
-    max_backups: u32, //The maximum number of old log files that can be retained
-    compress: bool,   //Whether to compress old log files
+    retention: Retention, //Limits on the count, total size and age of old log files
+    naming: BackupName,   //How old log files are named and found again
+    compress: Option<(CODEC, u32)>, //Codec and level of old log files, None for no compression
     cutmode: CUTMODE,
     timemode: MODE,
     filesize: u64,
     filehandle: File,
     startsec: u64,
+    rotate_at: u64, //When the time mode next rotates the file
+    check_interval: Duration, //How often the file is checked to still be at its path
+    checked_at: Instant,
+    lock: Option<Arc<std::fs::File>>, //Held around writes and rotation when processes share the file
+    durability: Durability, //When records are written out and synced to disk
+    buffer: Vec<u8>,        //Records not yet written to the file, lost if the handler is dropped unflushed
+    buffered_at: Instant,   //When the oldest record in the buffer arrived
+    appended: u64,          //Records put in the buffer, to tell whether a failed write buffered its record
+    unsynced: u32,          //Records written since the file was last synced
+    errors: Reporter,       //Where errors no caller sees are reported
+    failover: Option<Failover>, //Where records go while the file cannot be written
+    failures: u32,              //Consecutive failed writes
+    outage: Option<Outage>,     //Set while records go to the fallback
+    fallback: Option<File>,     //The fallback file, opened on the first record it takes
+    disk_guard: Option<DiskGuard>,      //What gives way when the volume runs short of space
+    space_checked_at: Option<Instant>, //When the free space was last looked up
+    short_of_space: bool,               //Set while records below the guard's level are dropped
 }
 
 impl FileHandler {
     pub async fn new(option: Box<dyn FileOption>) -> io::Result<FileHandler> {
+        Self::with_errors(option, Reporter::default()).await
+    }
+
+    /// Opens the file like `new`, reporting the errors of later rotations to `errors`.
+    pub(crate) async fn with_errors(option: Box<dyn FileOption>, errors: Reporter) -> io::Result<FileHandler> {
         let fo = option;
         let filename = fo.filename();
         let log_path = Path::new(&filename);
         let _ = mkdirs(log_path).await;
+        let lock = if fo.multi_process() { Some(Arc::new(lock_file(log_path)?)) } else { None };
+        acquire(&lock).await?;
+
+        let naming = BackupName::new(log_path, fo.backup_name());
+        let retention = Retention::new(fo.as_ref());
+        let compress = if fo.compress() { Some((fo.codec(), fo.compress_level())) } else { None };
+        let started = started_file(log_path);
+        let path = if fo.current_link() {
+            resume(log_path, &naming, compress, retention, fo.mode(), fo.timemode(), started.is_none() && fo.startup() != STARTUP::APPEND, &errors).await?
+        } else if fo.startup() == STARTUP::PERRUN {
+            started.unwrap_or_else(|| log_path.with_file_name(naming.run_name(timesec())))
+        } else {
+            match fs::metadata(log_path).await {
+                Ok(md) if started.is_none() && fo.startup() == STARTUP::ROTATE && md.len() > 0 => {
+                    let startsec = md.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                    rename(log_path, compress, retention, &naming, period(fo.mode(), startsec, fo.timemode()), &errors).await?
+                }
+                _ => {}
+            }
+            log_path.to_path_buf()
+        };
 
-        let file = Self::newfile(filename.clone()).await;
+        let file = Self::newfile(&path).await;
 
         if file.is_err() {
             return Err(file.err().unwrap());
@@ -59,36 +105,57 @@ impl FileHandler {
         let f = file.unwrap();
         let modified_time = f.metadata().await?.modified()?;
 
-        let datetime_utc: DateTime<chrono::Utc> = modified_time.into();
-        let datetime_local = datetime_utc.with_timezone(&Local);
-        let startsec = datetime_local.naive_local().and_utc().timestamp() as u64;
+        let startsec = modified_time.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        set_started_file(log_path, &path);
+        if fo.current_link() {
+            async_update_link(log_path, &path).await?;
+        }
 
         let fh = FileHandler {
             filename: fo.filename(),
+            filesize: fs::metadata(&path).await?.len(),
+            path,
+            link: fo.current_link(),
             max_size: fo.size(),
-            max_backups: fo.maxbackups(),
-            compress: fo.compress(),
+            retention,
+            naming,
+            compress,
             cutmode: fo.mode(),
             timemode: fo.timemode(),
-            filesize: fs::metadata(&log_path).await?.len(),
             filehandle: f,
             startsec,
+            rotate_at: next_rotation(startsec, fo.timemode()),
+            check_interval: fo.check_interval(),
+            checked_at: Instant::now(),
+            lock,
+            durability: fo.durability(),
+            buffer: Vec::new(),
+            buffered_at: Instant::now(),
+            appended: 0,
+            unsynced: 0,
+            errors,
+            failover: fo.failover(),
+            failures: 0,
+            outage: None,
+            fallback: None,
+            disk_guard: fo.disk_guard(),
+            space_checked_at: None,
+            short_of_space: false,
         };
+        fh.unlock();
 
         Ok(fh)
     }
 
     pub async fn new_from_clone(&mut self) -> io::Result<()> {
-        let filename = self.filename.clone();
-        let log_path = Path::new(&filename);
-        let _ = mkdirs(log_path).await;
-        let file = Self::newfile(filename).await?;
+        let _ = mkdirs(&self.path).await;
+        let file = Self::newfile(&self.path).await?;
         self.filesize = 0;
         self.filehandle = file;
         Ok(())
     }
 
-    async fn newfile(filename: String) -> io::Result<tokio::fs::File> {
+    async fn newfile(filename: impl AsRef<Path>) -> io::Result<tokio::fs::File> {
         OpenOptions::new().append(true).create(true).open(filename).await
     }
 
@@ -96,51 +163,399 @@ impl FileHandler {
         self.filename.clone()
     }
 
-    async fn rename(&self) -> io::Result<()> {
-        let log_path = Path::new(&self.filename);
-        match self.cutmode {
-            CUTMODE::TIME => rename(&log_path, self.compress, self.max_backups, Some(getbackup_with_time(self.startsec, self.timemode))).await,
-            CUTMODE::SIZE => rename(&log_path, self.compress, self.max_backups, None).await,
-            CUTMODE::MIXED => rename(&log_path, self.compress, self.max_backups, Some(getbackup_with_time(self.startsec, self.timemode))).await,
+    async fn rename(&mut self) -> io::Result<()> {
+        // tokio completes writes in the background; finish them before the file is archived
+        self.filehandle.flush().await?;
+        rename(&self.path, self.compress, self.retention, &self.naming, period(self.cutmode, self.startsec, self.timemode), &self.errors).await
+    }
+
+    async fn rotate(&mut self) -> io::Result<()> {
+        self.write_buffer().await?;
+        if self.link {
+            return self.switch().await;
         }
+        self.rename().await?;
+        self.new_from_clone().await
+    }
+
+    // Starts a new file for the period beginning now and points the link at it;
+    // the previous file is compressed and old files pruned in the background.
+    async fn switch(&mut self) -> io::Result<()> {
+        self.filehandle.flush().await?;
+        let link = Path::new(&self.filename);
+        let dir = log_dir(link)?;
+        let path = self.naming.free_path(&dir, period(self.cutmode, timesec(), self.timemode));
+        let file = Self::newfile(&path).await?;
+        if let Err(e) = async_update_link(link, &path).await {
+            let _ = fs::remove_file(&path).await;
+            return Err(e);
+        }
+        let previous = std::mem::replace(&mut self.path, path);
+        self.filehandle = file;
+        self.filesize = 0;
+        archive(previous, dir, self.compress, self.retention, &self.naming, &self.path, &self.errors);
+        Ok(())
     }
 
     pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_record(None, data).await
+    }
+
+    /// Writes a record of `level`, which decides whether it is synced to disk under `Durability::sync_level`.
+    pub async fn write_level(&mut self, level: LEVEL, data: &[u8]) -> io::Result<()> {
+        self.write_record(Some(level), data).await
+    }
+
+    async fn write_record(&mut self, level: Option<LEVEL>, data: &[u8]) -> io::Result<()> {
+        if self.dropped_for_space(level).await {
+            return Ok(());
+        }
+        if self.failover.is_none() {
+            return self.write_primary(level, data).await;
+        }
+        if let Some(outage) = &self.outage {
+            if !outage.retry_due() {
+                return self.divert(data).await;
+            }
+            // the marker and the record
+            let appended = self.appended + 2;
+            return match self.recover(level, data).await {
+                Ok(()) => {
+                    self.outage = None;
+                    self.fallback = None;
+                    Ok(())
+                }
+                Err(e) => {
+                    self.errors.report(FAILURE::WRITE, &self.filename, e);
+                    if let (Some(outage), Some(failover)) = (&mut self.outage, &self.failover) {
+                        outage.retry_failed(failover);
+                    }
+                    let pending = self.unwritten(data, appended);
+                    self.divert(&pending).await
+                }
+            };
+        }
+        let appended = self.appended + 1;
+        // tokio completes a write in the background, so a failure shows with the next write
+        match self.write_primary(level, data).await {
+            Ok(()) => {
+                self.failures = 0;
+                Ok(())
+            }
+            Err(e) => {
+                self.failures += 1;
+                match &self.failover {
+                    Some(failover) if self.failures >= failover.threshold => {
+                        self.errors.report(FAILURE::WRITE, &self.filename, e);
+                        self.outage = Some(Outage::new(failover));
+                        self.failures = 0;
+                        let pending = self.unwritten(data, appended);
+                        self.divert(&pending).await
+                    }
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+
+    // Retries the log file with a marker record noting the gap, then the record itself.
+    async fn recover(&mut self, level: Option<LEVEL>, data: &[u8]) -> io::Result<()> {
+        let marker = match (&self.outage, &self.failover) {
+            (Some(outage), Some(failover)) => outage.marker(&self.filename, &failover.fallback),
+            _ => String::new(),
+        };
+        self.reopen().await?;
+        self.write_primary(None, marker.as_bytes()).await?;
+        self.write_primary(level, data).await?;
+        self.write_buffer().await?;
+        self.filehandle.flush().await
+    }
+
+    // What a failed write leaves for the fallback: the records still buffered, and `data` too
+    // unless the buffer count reached `appended`, meaning it is among them.
+    fn unwritten(&mut self, data: &[u8], appended: u64) -> Vec<u8> {
+        let mut pending = std::mem::take(&mut self.buffer);
+        if self.appended != appended {
+            pending.extend_from_slice(data);
+        }
+        pending
+    }
+
+    // Writes a record to the fallback, or to stderr when the fallback file fails too.
+    async fn divert(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some(outage) = &mut self.outage {
+            outage.diverted();
+        }
+        if let Some(Failover { fallback: FALLBACK::FILE(path), .. }) = &self.failover {
+            if self.fallback.is_none() {
+                let _ = mkdirs(Path::new(path)).await;
+                match Self::newfile(path).await {
+                    Ok(f) => self.fallback = Some(f),
+                    Err(e) => self.errors.report(FAILURE::OPEN, path, e),
+                }
+            }
+            if let Some(f) = &mut self.fallback {
+                let r = match f.write_all(data).await {
+                    Ok(()) => f.flush().await,
+                    Err(e) => Err(e),
+                };
+                match r {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        self.errors.report(FAILURE::WRITE, path, e);
+                        self.fallback = None;
+                    }
+                }
+            }
+        }
+        io::stderr().write_all(data).await
+    }
+
+    // Looks up the free space at most once per check interval of the disk guard. While it is short,
+    // backups are deleted, oldest first; when that is not enough, records below the guard's level are dropped.
+    async fn dropped_for_space(&mut self, level: Option<LEVEL>) -> bool {
+        let Some(guard) = self.disk_guard else {
+            return false;
+        };
+        if guard.check_due(self.space_checked_at) {
+            self.space_checked_at = Some(Instant::now());
+            // an unknown free space counts as enough
+            let free = match log_dir(&self.path).and_then(|dir| free_space(&dir).map(|free| (dir, free))) {
+                Ok((dir, free)) if free < guard.min_free => match prune_for_space(&dir, &self.naming, &self.path, guard.min_free).await {
+                    Ok(free) => free,
+                    Err(e) => {
+                        self.errors.report(FAILURE::RETENTION, &dir, e);
+                        free_space(&dir).unwrap_or(free)
+                    }
+                },
+                _ => u64::MAX,
+            };
+            let short = free < guard.min_free;
+            if short && !self.short_of_space {
+                self.errors.report(FAILURE::SPACE, &self.filename, guard.shortage(free));
+            }
+            self.short_of_space = short;
+        }
+        // a raw write has no level, so it counts as the least important record
+        if self.short_of_space && level.is_none_or(|l| l < guard.level) {
+            self.errors.dropped();
+            return true;
+        }
+        false
+    }
+
+    async fn write_primary(&mut self, level: Option<LEVEL>, data: &[u8]) -> io::Result<()> {
+        if self.lock.is_none() {
+            return self.append(level, data).await;
+        }
+        acquire(&self.lock).await?;
+        // other processes only see what is in the file, so nothing stays buffered past the lock
+        let r = match self.follow().await {
+            Ok(()) => match self.append(level, data).await {
+                Ok(()) => match self.write_buffer().await {
+                    Ok(()) => self.filehandle.flush().await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        self.unlock();
+        r
+    }
+
+    fn unlock(&self) {
+        if let Some(lock) = &self.lock {
+            let _ = lock.unlock();
+        }
+    }
+
+    // Picks up a rotation another process made while this one waited for the lock,
+    // and the size the file has now that other processes write to it too.
+    async fn follow(&mut self) -> io::Result<()> {
+        let mut rotated = self.is_moved().await;
+        if self.link {
+            if let Ok(target) = fs::read_link(&self.filename).await {
+                let target = log_dir(Path::new(&self.filename))?.join(target);
+                if target != self.path {
+                    self.path = target;
+                    rotated = true;
+                }
+            }
+        }
+        if rotated {
+            self.reopen().await?;
+            // the other process has rotated for the period this one was about to rotate for
+            if timesec() >= self.rotate_at {
+                self.startsec = timesec();
+                self.rotate_at = next_rotation(self.startsec, self.timemode);
+            }
+        }
+        self.filesize = self.filehandle.metadata().await?.len();
+        Ok(())
+    }
+
+    async fn append(&mut self, level: Option<LEVEL>, data: &[u8]) -> io::Result<()> {
+        if !self.check_interval.is_zero() && self.checked_at.elapsed() >= self.check_interval {
+            self.checked_at = Instant::now();
+            if self.is_moved().await {
+                if let Err(e) = self.reopen().await {
+                    self.errors.report(FAILURE::ROTATE, &self.filename, e);
+                }
+            }
+        }
         match self.cutmode {
             CUTMODE::TIME => {
-                if passtimemode(self.startsec, self.timemode) {
-                    let ack = self.rename().await;
-                    if ack.is_ok() {
-                        let _ = self.new_from_clone().await;
-                        self.startsec = timesec();
-                    }
+                if timesec() >= self.rotate_at && self.rotate_for_write().await {
+                    self.startsec = timesec();
+                    self.rotate_at = next_rotation(self.startsec, self.timemode);
                 }
             }
             CUTMODE::SIZE => {
                 if self.max_size > 0 && self.filesize + data.len() as u64 > self.max_size {
-                    let ack = self.rename().await;
-                    if ack.is_ok() {
-                        let _ = self.new_from_clone().await?;
-                    }
+                    self.rotate_for_write().await;
                 }
             }
             CUTMODE::MIXED => {
-                if passtimemode(self.startsec, self.timemode) || self.max_size > 0 && self.filesize + data.len() as u64 > self.max_size {
-                    let ack = self.rename().await;
-                    if ack.is_ok() {
-                        let _ = self.new_from_clone().await;
-                        self.startsec = timesec();
-                    }
+                if (timesec() >= self.rotate_at || self.max_size > 0 && self.filesize + data.len() as u64 > self.max_size) && self.rotate_for_write().await {
+                    self.startsec = timesec();
+                    self.rotate_at = next_rotation(self.startsec, self.timemode);
                 }
             }
         }
-        let fh = self.filehandle.borrow_mut();
-        fh.write_all(data).await?;
         self.filesize += data.len() as u64;
+        if self.durability.buffer_size == 0 {
+            let fh = self.filehandle.borrow_mut();
+            fh.write_all(data).await?;
+        } else {
+            if self.buffer.is_empty() {
+                self.buffered_at = Instant::now();
+            }
+            self.buffer.extend_from_slice(data);
+            self.appended += 1;
+            if self.durability.buffer_due(self.buffer.len(), self.buffered_at) {
+                self.write_buffer().await?;
+            }
+        }
+        self.unsynced += 1;
+        if self.durability.sync_due(level, self.unsynced) {
+            self.write_buffer().await?;
+            self.filehandle.flush().await?;
+            self.filehandle.sync_data().await?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    // Rotates ahead of a write; a failure is reported and the record goes to the current file.
+    async fn rotate_for_write(&mut self) -> bool {
+        match self.rotate().await {
+            Ok(()) => true,
+            Err(e) => {
+                self.errors.report(FAILURE::ROTATE, &self.filename, e);
+                false
+            }
+        }
+    }
+
+    // Writes the buffered records to the file. What the file does not take stays buffered,
+    // for the next write or the failover.
+    async fn write_buffer(&mut self) -> io::Result<()> {
+        while !self.buffer.is_empty() {
+            match self.filehandle.write(&self.buffer).await {
+                Ok(0) => return self.keep_unwritten(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.buffer.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return self.keep_unwritten(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn keep_unwritten(&mut self, e: io::Error) -> io::Result<()> {
+        self.durability.trim_unwritten(&mut self.buffer);
+        Err(e)
+    }
+
+    /// Writes the buffer once its oldest record has waited the flush interval, for the writer thread
+    /// to call while no records arrive. Returns how long until it may next be due.
+    pub(crate) async fn write_due(&mut self) -> Option<Duration> {
+        let wait = self.durability.flush_wait(!self.buffer.is_empty(), self.buffered_at)?;
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+        // tokio completes the write in the background until the file is flushed
+        let r = match self.write_buffer().await {
+            Ok(()) => self.filehandle.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = r {
+            self.errors.report(FAILURE::WRITE, &self.filename, e);
+        }
+        Some(self.durability.flush_interval)
+    }
+
+    /// Flushes buffered data and syncs the log file to disk.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer().await?;
+        self.filehandle.flush().await?;
+        self.filehandle.sync_all().await?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Reopens the configured path without renaming anything, for when an external tool such as
+    /// logrotate has moved or truncated the file. The rotation schedule is left as it was.
+    pub async fn reopen(&mut self) -> io::Result<()> {
+        self.write_buffer().await?;
+        self.filehandle.flush().await?;
+        mkdirs(&self.path).await?;
+        let file = Self::newfile(&self.path).await?;
+        if self.link {
+            async_update_link(Path::new(&self.filename), &self.path).await?;
+        }
+        self.filesize = file.metadata().await?.len();
+        self.filehandle = file;
+        Ok(())
+    }
+
+    // Whether the open file was deleted or moved away from its path.
+    async fn is_moved(&self) -> bool {
+        match fs::metadata(&self.path).await {
+            Ok(at_path) => self.filehandle.metadata().await.is_ok_and(|open| !same_file(&open, &at_path)),
+            Err(_) => true,
+        }
+    }
+
+    /// Rotates the file now, with the same renaming, compression and retention as a scheduled rotation.
+    pub async fn rotate_now(&mut self) -> io::Result<()> {
+        acquire(&self.lock).await?;
+        let r = self.force_rotate().await;
+        self.unlock();
+        r
+    }
+
+    async fn force_rotate(&mut self) -> io::Result<()> {
+        self.rotate().await?;
+        self.startsec = timesec();
+        self.rotate_at = next_rotation(self.startsec, self.timemode);
         Ok(())
     }
 }
 
+// Waits for the lock on a blocking thread, as other processes may hold it for a while.
+async fn acquire(lock: &Option<Arc<std::fs::File>>) -> io::Result<()> {
+    match lock {
+        Some(lock) => {
+            let lock = lock.clone();
+            tokio::task::spawn_blocking(move || lock.lock()).await.map_err(io::Error::other)?
+        }
+        None => Ok(()),
+    }
+}
+
 async fn mkdirs(dir_path: &Path) -> io::Result<()> {
     let parent_dir = dir_path.parent().ok_or_else(|| Error::new(ErrorKind::Other, ErrCode::NotFound.to_string()))?;
     if !parent_dir.exists() {
@@ -149,10 +564,59 @@ async fn mkdirs(dir_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
-async fn rename(log_path: &Path, compress: bool, maxbackup: u32, backupsuffix: Option<String>) -> io::Result<()> {
+// The file a link-mode handler writes to: the link's target while it exists, unless a `fresh` start
+// archives a non-empty target, otherwise a new file for the current period. A regular file left at
+// the link's path is rotated away first.
+#[allow(clippy::too_many_arguments)]
+async fn resume(link: &Path, naming: &BackupName, compress: Option<(CODEC, u32)>, retention: Retention, cutmode: CUTMODE, timemode: MODE, fresh: bool, errors: &Reporter) -> io::Result<PathBuf> {
+    let dir = log_dir(link)?;
+    let previous = match fs::symlink_metadata(link).await {
+        Ok(md) if md.file_type().is_symlink() => match fs::read_link(link).await {
+            Ok(target) if dir.join(&target).is_file() => {
+                let target = dir.join(target);
+                if !fresh || fs::metadata(&target).await?.len() == 0 {
+                    return Ok(target);
+                }
+                Some(target)
+            }
+            _ => None,
+        },
+        Ok(md) => {
+            let startsec = md.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let backup = naming.free_path(&dir, period(cutmode, startsec, timemode));
+            fs::rename(link, &backup).await?;
+            Some(backup)
+        }
+        Err(_) => None,
+    };
+    let path = naming.free_path(&dir, period(cutmode, timesec(), timemode));
+    if let Some(previous) = previous {
+        archive(previous, dir, compress, retention, naming, &path, errors);
+    }
+    Ok(path)
+}
+
+// Compresses `backup` and prunes the old files in the background, leaving `active` alone.
+fn archive(backup: PathBuf, dir: PathBuf, compress: Option<(CODEC, u32)>, retention: Retention, naming: &BackupName, active: &Path, errors: &Reporter) {
+    let naming = naming.clone();
+    let active = active.to_path_buf();
+    let errors = errors.clone();
+    tokio::spawn(async move {
+        if let Some((codec, level)) = compress {
+            if let Err(e) = async_compress(backup.to_str().unwrap(), codec, level).await {
+                errors.report(FAILURE::COMPRESS, &backup, e);
+            }
+        }
+        if retention.is_limited() {
+            if let Err(e) = maxbackup_with_size(&dir, &naming, retention, &active).await {
+                errors.report(FAILURE::RETENTION, &dir, e);
+            }
+        }
+    });
+}
+
+async fn rename(log_path: &Path, compress: Option<(CODEC, u32)>, retention: Retention, naming: &BackupName, time: Option<(u64, MODE)>, errors: &Reporter) -> io::Result<()> {
     let mut counter = 1;
-    let file_stem = log_path.file_stem().unwrap_or_else(|| OsStr::new("tklog"));
-    let extension = log_path.extension().map_or("", |e| e.to_str().unwrap()).to_owned();
     let mut maxloop = 1 << 20;
     while maxloop > 0 {
         let mut parent = log_path.parent().ok_or_else(|| Error::new(ErrorKind::Other, ErrCode::NotFound.to_string()))?.to_path_buf();
@@ -164,47 +628,25 @@ async fn rename(log_path: &Path, compress: bool, maxbackup: u32, backupsuffix: O
         }
 
         maxloop -= 1;
-        let mut suffix = String::new();
-        if !extension.is_empty() {
-            suffix.push('.');
-            suffix.push_str(extension.as_str());
-        }
-
-        let new_name: String;
-
-        if backupsuffix.is_some() {
-            new_name = format!("{}_{}_{}{}", file_stem.to_string_lossy(), backupsuffix.as_ref().unwrap(), counter, suffix);
-        } else {
-            new_name = format!("{}_{}{}", file_stem.to_string_lossy(), counter, suffix);
-        }
+        let new_path = parent.join(naming.name(time, counter));
 
-        let new_path = parent.join(&new_name);
-
-        let new_path_gz = parent.join(format!("{}.gz", new_path.display().to_string()));
-        if !new_path.exists() && !new_path_gz.exists() {
-            let r = fs::rename(log_path, &new_path).await;
-            if r.is_err() && maxloop <= 0 {
-                return Err(r.err().unwrap());
-            } else {
-                let fname = file_stem.to_string_lossy().to_string().clone();
-                tokio::spawn(async move {
-                    if compress {
-                        let _ = async_gzip(new_path.to_str().unwrap()).await;
-                    }
-                    if maxbackup > 0 {
-                        let _ = maxbackup_with_size(&parent, extension, fname, maxbackup).await;
-                    }
-                });
-                return Ok(());
-            }
+        if !new_path.exists() && !compressed_exists(&new_path) {
+            // a failed rename leaves the file in place, so the caller keeps writing to it and retries
+            fs::rename(log_path, &new_path).await?;
+            archive(new_path, parent, compress, retention, naming, log_path, errors);
+            return Ok(());
         }
         counter += 1;
     }
     Ok(())
 }
 
-async fn filter_files(dir_path: &Path, extension: String, filename: String, maxbackup: u32) -> io::Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
+async fn filter_files(dir_path: &Path, naming: &BackupName, retention: Retention, active: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(retention.expired(backups(dir_path, naming, active).await?))
+}
+
+// The backups in `dir_path` as (modified seconds, size, path).
+async fn backups(dir_path: &Path, naming: &BackupName, active: &Path) -> io::Result<Vec<(u64, u64, PathBuf)>> {
     let mut sortvec = Vec::new();
     let mut entries = fs::read_dir(dir_path).await?;
     while let Some(entry) = entries.next_entry().await? {
@@ -216,25 +658,27 @@ async fn filter_files(dir_path: &Path, extension: String, filename: String, maxb
         let sec = md.modified()?.duration_since(std::time::UNIX_EPOCH).expect("").as_secs();
 
         if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            let mut suffix = String::new();
-            if !extension.is_empty() {
-                suffix.push_str("\\.");
-                suffix.push_str(extension.as_str());
-            }
-            let parrent = format!("{}{}{}{}{}{}", "^", filename, "(_\\d+){0,}", "_\\d+", suffix, "(\\.gz){0,}$");
-            let re = Regex::new(parrent.as_str()).unwrap();
-            if re.is_match(file_name) {
-                sortvec.push((sec, path.clone()))
+            if naming.matches(file_name) && active.file_name() != Some(OsStr::new(file_name)) {
+                sortvec.push((sec, md.len(), path.clone()))
             }
         }
     }
-    sortvec.sort_by(|a, b| a.0.cmp(&b.0));
-    if sortvec.len() > maxbackup as usize {
-        for tuple in sortvec.iter().take(sortvec.len() - maxbackup as usize) {
-            files.push(tuple.1.clone());
+    Ok(sortvec)
+}
+
+// Deletes backups, oldest first, until `dir` has `min_free` bytes free again; returns the free space left.
+async fn prune_for_space(dir: &Path, naming: &BackupName, active: &Path, min_free: u64) -> io::Result<u64> {
+    let mut backups = backups(dir, naming, active).await?;
+    backups.sort_by_key(|b| b.0);
+    let mut free = free_space(dir)?;
+    for (_, _, path) in backups {
+        if free >= min_free {
+            break;
         }
+        fs::remove_file(path).await?;
+        free = free_space(dir)?;
     }
-    Ok(files)
+    Ok(free)
 }
 
 async fn delete_files(files: Vec<PathBuf>) -> io::Result<()> {
@@ -243,7 +687,7 @@ async fn delete_files(files: Vec<PathBuf>) -> io::Result<()> {
     })
 }
 
-async fn maxbackup_with_size(parant: &PathBuf, extension: String, filename: String, maxbackup: u32) -> io::Result<()> {
-    let matched_files = filter_files(parant, extension, filename, maxbackup).await?;
+async fn maxbackup_with_size(parant: &PathBuf, naming: &BackupName, retention: Retention, active: &Path) -> io::Result<()> {
+    let matched_files = filter_files(parant, naming, retention, active).await?;
     delete_files(matched_files).await
 }
@@ -17,6 +17,12 @@
 // Trace log macros, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_traces {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Trace, $($arg),*);
     };
@@ -26,6 +32,12 @@ macro_rules! async_traces {
 //Debug log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_debugs {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Debug, $($arg),*);
     };
@@ -35,6 +47,12 @@ macro_rules! async_debugs {
 //Info log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_infos {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Info, $($arg),*);
     };
@@ -44,6 +62,12 @@ macro_rules! async_infos {
 // warn log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_warns {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Warn, $($arg),*);
     };
@@ -53,6 +77,12 @@ macro_rules! async_warns {
 // Error log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_errors {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Error, $($arg),*);
     };
@@ -62,6 +92,12 @@ macro_rules! async_errors {
 // Fatal log macros, call secondary macro processing logic
 #[macro_export]
 macro_rules! async_fatals {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::async_logs_common!($logger, $crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::async_logs_common!($logger, $crate::LEVEL::Fatal, $($arg),*);
     };
@@ -71,7 +107,7 @@ macro_rules! async_fatals {
 #[macro_export]
 macro_rules! async_formats {
     ($logger:expr, $level:expr, $($arg:expr),*) => {
-        unsafe {
+        {
             let logger_lock:&mut Arc<tokio::sync::Mutex<tklog::Async::Logger>> = $logger;
             let mut logger = logger_lock.as_ref().lock().await;
             let level:$crate::LEVEL = $level;
@@ -95,8 +131,8 @@ macro_rules! async_formats {
 
 #[macro_export]
 macro_rules! async_logs_common {
-    ($logger:expr, $level:expr, $($arg:expr),*) => {
-        unsafe {
+    ($logger:expr, $level:expr, [$($key:ident $(:$capture:tt)? = $value:expr),*], $($arg:expr),*) => {
+        {
             let logger_lock:&mut Arc<tokio::sync::Mutex<tklog::Async::Logger>> = $logger;
             let mut logger = logger_lock.as_ref().lock().await;
             let module = module_path!();
@@ -109,12 +145,16 @@ macro_rules! async_logs_common {
                     line = line!();
                 }
                 let msg: String = formatted_args.join(logger.get_separator().as_str());
-                let ss = logger.fmt(module,$level, file, line, msg);
+                let fields: Vec<(String, $crate::FieldValue)> = vec![$((stringify!($key).to_string(), $crate::field_value!($($capture)? $value))),*];
+                let ss = logger.fmt_fields(module,$level, file, line, msg, fields);
                 if !ss.is_empty(){
                     logger.print($level,module,ss.as_str()).await;
                 }
             }
         }
     };
+    ($logger:expr, $level:expr, $($arg:expr),*) => {
+        $crate::async_logs_common!($logger, $level, [], $($arg),*);
+    };
     () => {};
 }
@@ -0,0 +1,580 @@
+// Copyright (c) 2024, donnie4w <donnie4w@gmail.com>
+// All rights reserved.
+// https://github.com/donnie4w/tklog
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logger configuration loaded from TOML or JSON.
+//!
+//! ```toml
+//! level = "info"
+//! timezone = "utc"
+//! directives = "hyper=warn"
+//! format = ["LevelFlag", "Date", "Time", "ShortFileName"]
+//! printmode = "delay"
+//!
+//! [file]
+//! path = "logs/app.log"
+//! mode = "day"
+//! max_backups = 10
+//! compress = true
+//! codec = "gzip"
+//! compress_level = 6
+//! max_total_size = 1073741824
+//! max_age = "14d"
+//! backup_name = "{stem}.{YYYY}-{MM}-{DD}.{ext}"
+//! check_interval = "10s"
+//! current_link = false
+//! startup = "append"
+//! multi_process = false
+//! buffer_size = 65536
+//! flush_interval = "1s"
+//! sync_every = 0
+//! sync_level = "error"
+//! fallback = "/tmp/app.log"
+//! fallback_threshold = 3
+//! fallback_retry = "1s"
+//! fallback_max_retry = "1m"
+//! min_free_space = 536870912
+//! low_space_level = "warn"
+//!
+//! [modules."my_app::db"]
+//! level = "trace"
+//! console = false
+//! file = { path = "logs/db.log", max_size = 10485760 }
+//!
+//! [levels.error]
+//! format = ["LevelFlag", "Date", "Time", "LongFileName"]
+//! ```
+//!
+//! A `file` rotates by size when only `max_size` is set, by time when only `mode` is set
+//! (the default, daily) and by both when both are set.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use std::{fmt, fs, io, thread};
+
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+use crate::handle::{FileMixedMode, FileOption, FileSizeMode, FileTimeMode};
+use crate::{naming, parse_directives, schedule::Schedule, Directive, DiskGuard, Durability, Failover, Format, LogOption, Weekday, CODEC, FALLBACK, LEVEL, MODE, PRINTMODE, STARTUP, TIMEZONE};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(String, io::Error),
+    /// The file is not valid TOML or JSON, or holds an unknown key or value.
+    Parse(String),
+    /// The values parse but cannot be used together.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read log config {}: {}", path, e),
+            ConfigError::Parse(e) => write!(f, "invalid log config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid log config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The whole logger configuration. Every key is optional; unset keys leave the logger unchanged.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub level: Option<LEVEL>,
+    /// `RUST_LOG` style directives, applied after `modules`.
+    pub directives: Option<Directives>,
+    pub console: Option<bool>,
+    pub format: Option<Formats>,
+    pub formatter: Option<String>,
+    pub printmode: Option<PrintMode>,
+    pub separator: Option<String>,
+    /// `local`, `utc` or an offset such as `+08:00`; process-wide, see `set_timezone`.
+    pub timezone: Option<TIMEZONE>,
+    pub file: Option<FileConfig>,
+    #[serde(default)]
+    pub modules: BTreeMap<String, OptionConfig>,
+    #[serde(default)]
+    pub levels: BTreeMap<LEVEL, OptionConfig>,
+}
+
+/// The options of a module or a level, see `set_mod_option` and `set_level_option`.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OptionConfig {
+    /// Ignored for level options.
+    pub level: Option<LEVEL>,
+    pub console: Option<bool>,
+    pub format: Option<Formats>,
+    pub formatter: Option<String>,
+    pub file: Option<FileConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub path: String,
+    /// minute, hour, day, week, month or a cron-like schedule such as `"0 */6 * * *"`.
+    pub mode: Option<MODE>,
+    /// The first day of the week for `mode = "week"`; Monday by default.
+    pub week_start: Option<String>,
+    pub max_size: Option<u64>,
+    #[serde(default)]
+    pub max_backups: u32,
+    #[serde(default)]
+    pub compress: bool,
+    /// Implies `compress`; gzip when only `compress_level` is set.
+    pub codec: Option<CODEC>,
+    pub compress_level: Option<u32>,
+    /// Deletes the oldest backups once all backups together exceed this many bytes.
+    pub max_total_size: Option<u64>,
+    /// Deletes backups older than this, e.g. `"14d"`.
+    pub max_age: Option<Age>,
+    /// The template backups are named after, e.g. `"{stem}.{YYYY}-{MM}-{DD}T{hh}.{ext}"`.
+    pub backup_name: Option<String>,
+    /// How often to check that the file is still at `path`, recreating it after it was deleted or moved, e.g. `"10s"`.
+    pub check_interval: Option<Age>,
+    /// Writes to files named like backups and keeps `path` as a symlink to the current one.
+    #[serde(default)]
+    pub current_link: bool,
+    /// append (the default), rotate or per_run; see `STARTUP`.
+    pub startup: Option<STARTUP>,
+    /// Set when several processes write to `path`; they take turns through a lock file.
+    #[serde(default)]
+    pub multi_process: bool,
+    /// Collects records in memory up to this many bytes before writing them; see `Durability`.
+    #[serde(default)]
+    pub buffer_size: usize,
+    /// Writes buffered records once the oldest has waited this long, e.g. `"1s"`.
+    pub flush_interval: Option<Age>,
+    /// Syncs the file to disk after this many records; 1 for audit logs.
+    #[serde(default)]
+    pub sync_every: u32,
+    /// Syncs the file to disk after a record of this level or above.
+    pub sync_level: Option<LEVEL>,
+    /// A path, or `"stderr"`, to write to while `path` cannot be written; see `Failover`.
+    pub fallback: Option<String>,
+    /// Consecutive failed writes before switching to `fallback`; 3 by default.
+    pub fallback_threshold: Option<u32>,
+    /// The wait before `path` is first retried, doubled after each failed retry; 1 second by default.
+    pub fallback_retry: Option<Age>,
+    /// The longest wait between retries; 1 minute by default.
+    pub fallback_max_retry: Option<Age>,
+    /// Deletes backups, then drops minor records, while the volume has fewer bytes free; see `DiskGuard`.
+    pub min_free_space: Option<u64>,
+    /// The lowest level still written while space is short; error by default.
+    pub low_space_level: Option<LEVEL>,
+    /// How often the free space is looked up; 1 second by default.
+    pub space_check_interval: Option<Age>,
+}
+
+/// Format flag names such as `["LevelFlag", "Date", "Time"]`, combined into a `Format` value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Formats(pub u8);
+
+#[derive(Debug, Clone)]
+pub struct Directives(pub Vec<Directive>);
+
+/// A duration written as seconds or as a string with a unit: `"90s"`, `"30m"`, `"12h"` or `"14d"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Age(pub Duration);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintMode {
+    Delay,
+    Punctual,
+}
+
+impl Config {
+    /// Reads a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.display().to_string(), e))?;
+        Self::from_content(path, &content)
+    }
+
+    pub(crate) fn from_content(path: &Path, content: &str) -> Result<Self, ConfigError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(content),
+            Some("json") => Self::from_json(content),
+            _ => Err(ConfigError::Invalid(format!("{}: expected a .toml or .json file", path.display()))),
+        }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// Checks the combined values and builds the options to apply, so a config that
+    /// fails here leaves the logger untouched.
+    pub(crate) fn settings(&self) -> Result<Settings, ConfigError> {
+        let option = LogOption {
+            level: self.level,
+            format: self.format.map(|f| f.0),
+            formatter: self.formatter.clone(),
+            console: self.console,
+            fileoption: file_option(self.file.as_ref(), "file")?,
+        };
+        let mut modules = Vec::new();
+        for (module, o) in &self.modules {
+            if module.is_empty() {
+                return Err(ConfigError::Invalid("modules: the module name is empty".to_string()));
+            }
+            modules.push((module.clone(), o.to_option(&format!("modules.\"{}\".file", module))?));
+        }
+        let mut levels = Vec::new();
+        for (level, o) in &self.levels {
+            if *level == LEVEL::Off {
+                return Err(ConfigError::Invalid("levels: \"off\" cannot have options".to_string()));
+            }
+            levels.push((*level, o.to_option(&format!("levels.{:?}.file", level).to_lowercase())?));
+        }
+        Ok(Settings {
+            timezone: self.timezone,
+            printmode: self.printmode.map(|m| match m {
+                PrintMode::Delay => PRINTMODE::DELAY,
+                PrintMode::Punctual => PRINTMODE::PUNCTUAL,
+            }),
+            separator: self.separator.clone(),
+            option,
+            modules,
+            levels,
+            directives: self.directives.clone().map(|d| d.0).unwrap_or_default(),
+        })
+    }
+}
+
+impl OptionConfig {
+    fn to_option(&self, key: &str) -> Result<LogOption, ConfigError> {
+        Ok(LogOption { level: self.level, format: self.format.map(|f| f.0), formatter: self.formatter.clone(), console: self.console, fileoption: file_option(self.file.as_ref(), key)? })
+    }
+}
+
+fn file_option(file: Option<&FileConfig>, key: &str) -> Result<Option<Box<dyn FileOption>>, ConfigError> {
+    let Some(f) = file else {
+        return Ok(None);
+    };
+    if f.path.is_empty() {
+        return Err(ConfigError::Invalid(format!("{}: path is empty", key)));
+    }
+    if f.max_size == Some(0) {
+        return Err(ConfigError::Invalid(format!("{}: max_size must be greater than 0", key)));
+    }
+    let mode = match (f.mode, &f.week_start) {
+        (Some(MODE::WEEK(_)), Some(day)) => Some(MODE::WEEK(Weekday::from_str(day).map_err(|_| ConfigError::Invalid(format!("{}: unknown week_start \"{}\"", key, day)))?)),
+        (_, Some(_)) => return Err(ConfigError::Invalid(format!("{}: week_start needs mode = \"week\"", key))),
+        (mode, None) => mode,
+    };
+    if let Some(template) = &f.backup_name {
+        naming::check_template(template).map_err(|e| ConfigError::Invalid(format!("{}: {}", key, e)))?;
+    }
+    let compression = match (f.codec, f.compress_level) {
+        (None, None) => None,
+        (codec, level) => Some((codec.unwrap_or(CODEC::GZIP), level.unwrap_or(0))),
+    };
+    let max_age = f.max_age.map_or(Duration::ZERO, |a| a.0);
+    let failover = match &f.fallback {
+        Some(fallback) if *fallback == f.path => return Err(ConfigError::Invalid(format!("{}: fallback must differ from path", key))),
+        Some(fallback) => {
+            let target = if fallback == "stderr" { FALLBACK::STDERR } else { FALLBACK::FILE(fallback.clone()) };
+            let mut failover = Failover::new(target);
+            if let Some(failures) = f.fallback_threshold {
+                failover = failover.with_threshold(failures);
+            }
+            let (retry, max_retry) = (f.fallback_retry.map_or(failover.retry, |a| a.0), f.fallback_max_retry.map_or(failover.max_retry, |a| a.0));
+            Some(failover.with_retry(retry, max_retry))
+        }
+        None if f.fallback_threshold.is_some() || f.fallback_retry.is_some() || f.fallback_max_retry.is_some() => return Err(ConfigError::Invalid(format!("{}: fallback_threshold, fallback_retry and fallback_max_retry need fallback", key))),
+        None => None,
+    };
+    let disk_guard = match f.min_free_space {
+        Some(min_free) => {
+            let guard = DiskGuard::new(min_free);
+            Some(guard.with_level(f.low_space_level.unwrap_or(guard.level)).with_check_interval(f.space_check_interval.map_or(guard.check_interval, |a| a.0)))
+        }
+        None if f.low_space_level.is_some() || f.space_check_interval.is_some() => return Err(ConfigError::Invalid(format!("{}: low_space_level and space_check_interval need min_free_space", key))),
+        None => None,
+    };
+    let durability = Durability { buffer_size: f.buffer_size, flush_interval: f.flush_interval.map_or(Duration::ZERO, |a| a.0), sync_every: f.sync_every, sync_level: f.sync_level };
+    macro_rules! configure {
+        ($o:expr) => {{
+            let mut o = $o.with_retention(f.max_total_size.unwrap_or(0), max_age);
+            if let Some(template) = &f.backup_name {
+                o = o.with_backup_name(template);
+            }
+            if let Some(interval) = f.check_interval {
+                o = o.with_check_interval(interval.0);
+            }
+            if f.current_link {
+                o = o.with_current_link();
+            }
+            if let Some(startup) = f.startup {
+                o = o.with_startup(startup);
+            }
+            if f.multi_process {
+                o = o.with_multi_process();
+            }
+            o = o.with_durability(durability);
+            if let Some(failover) = &failover {
+                o = o.with_failover(failover.clone());
+            }
+            if let Some(guard) = disk_guard {
+                o = o.with_disk_guard(guard);
+            }
+            Box::new(match compression {
+                Some((codec, level)) => o.with_compression(codec, level),
+                None => o,
+            })
+        }};
+    }
+    let option: Box<dyn FileOption> = match (f.max_size, mode) {
+        (Some(size), Some(mode)) => configure!(FileMixedMode::new(&f.path, size, mode, f.max_backups, f.compress)),
+        (Some(size), None) => configure!(FileSizeMode::new(&f.path, size, f.max_backups, f.compress)),
+        (None, mode) => configure!(FileTimeMode::new(&f.path, mode.unwrap_or(MODE::DAY), f.max_backups, f.compress)),
+    };
+    Ok(Some(option))
+}
+
+/// Polls `path` every `interval` on a background thread and calls `on_change` whenever its
+/// modification time or length changes. No platform-specific file watcher is needed.
+/// The thread runs until the returned `Watcher` is dropped.
+///
+/// A standalone logger can be reloaded with it:
+///
+/// ```no_run
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// let logger = Arc::new(Mutex::new(tklog::sync::Logger::new()));
+/// logger.lock().unwrap().load_config("log.toml").unwrap();
+/// let l = logger.clone();
+/// let watcher = tklog::config::watch("log.toml", Duration::from_secs(5), move || {
+///     let _ = l.lock().unwrap().reload();
+/// });
+/// // ...
+/// drop(watcher);
+/// ```
+pub fn watch(path: impl AsRef<Path>, interval: Duration, mut on_change: impl FnMut() + Send + 'static) -> Watcher {
+    let path = path.as_ref().to_path_buf();
+    let stamp = move || -> Option<(Option<SystemTime>, u64)> { fs::metadata(&path).ok().map(|m| (m.modified().ok(), m.len())) };
+    let mut last = stamp();
+    let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+    thread::spawn(move || loop {
+        // nothing is ever sent, so this waits the whole interval unless the watcher is dropped
+        if let Err(RecvTimeoutError::Disconnected) = stopped.recv_timeout(interval) {
+            return;
+        }
+        let now = stamp();
+        // a file that is briefly missing while being replaced is not a change
+        if now.is_some() && now != last {
+            on_change();
+        }
+        if now.is_some() {
+            last = now;
+        }
+    });
+    Watcher { _stop: stop }
+}
+
+/// Watches a file for `watch`; dropping it stops the polling thread.
+/// A callback that is running at that moment still finishes.
+#[must_use = "the file is only watched while the Watcher is kept"]
+pub struct Watcher {
+    _stop: Sender<()>,
+}
+
+/// Calls `f` on a background thread each time the process receives SIGHUP.
+/// The handler is installed before this returns.
+#[cfg(unix)]
+pub fn on_sighup(f: impl FnMut() + Send + 'static) -> io::Result<()> {
+    on_signal(tokio::signal::unix::SignalKind::hangup(), f)
+}
+
+/// Calls `f` on a background thread each time the process receives `kind`.
+/// The handler is installed before this returns.
+#[cfg(unix)]
+pub fn on_signal(kind: tokio::signal::unix::SignalKind, mut f: impl FnMut() + Send + 'static) -> io::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let mut received = {
+        let _guard = rt.enter();
+        tokio::signal::unix::signal(kind)?
+    };
+    // `f` runs outside the runtime, so it may block on a runtime of its own
+    thread::spawn(move || {
+        while rt.block_on(received.recv()).is_some() {
+            f();
+        }
+    });
+    Ok(())
+}
+
+/// A validated `Config`, ready for a logger to apply.
+pub(crate) struct Settings {
+    pub timezone: Option<TIMEZONE>,
+    pub printmode: Option<PRINTMODE>,
+    pub separator: Option<String>,
+    pub option: LogOption,
+    pub modules: Vec<(String, LogOption)>,
+    pub levels: Vec<(LEVEL, LogOption)>,
+    pub directives: Vec<Directive>,
+}
+
+impl<'de> Deserialize<'de> for LEVEL {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        LEVEL::from_str(&s).map_err(|_| de::Error::custom(format!("unknown level \"{}\", expected one of trace, debug, info, warn, error, fatal, off", s)))
+    }
+}
+
+impl<'de> Deserialize<'de> for TIMEZONE {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TIMEZONE::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for MODE {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "minute" => Ok(MODE::MINUTE),
+            "hour" => Ok(MODE::HOUR),
+            "day" => Ok(MODE::DAY),
+            "week" => Ok(MODE::WEEK(Weekday::Mon)),
+            "month" => Ok(MODE::MONTH),
+            // anything else with several fields or a shorthand is a cron-like schedule
+            m if m.contains(' ') || m.starts_with('@') => Schedule::from_str(&s).map(MODE::CRON).map_err(de::Error::custom),
+            _ => Err(de::Error::custom(format!("unknown rotation mode \"{}\", expected one of minute, hour, day, week, month or a cron-like schedule", s))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CODEC {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "gzip" => Ok(CODEC::GZIP),
+            #[cfg(feature = "zstd")]
+            "zstd" => Ok(CODEC::ZSTD),
+            #[cfg(feature = "xz")]
+            "xz" => Ok(CODEC::XZ),
+            #[cfg(feature = "bzip2")]
+            "bzip2" => Ok(CODEC::BZIP2),
+            #[cfg(feature = "lz4")]
+            "lz4" => Ok(CODEC::LZ4),
+            #[allow(unreachable_patterns)]
+            name @ ("zstd" | "xz" | "bzip2" | "lz4") => Err(de::Error::custom(format!("codec \"{}\" needs the \"{}\" feature of tklog", s, name))),
+            _ => Err(de::Error::custom(format!("unknown codec \"{}\", expected one of gzip, zstd, xz, bzip2, lz4", s))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for STARTUP {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "append" => Ok(STARTUP::APPEND),
+            "rotate" => Ok(STARTUP::ROTATE),
+            "per_run" | "perrun" => Ok(STARTUP::PERRUN),
+            _ => Err(de::Error::custom(format!("unknown startup \"{}\", expected one of append, rotate, per_run", s))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Age {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AgeVisitor;
+
+        impl de::Visitor<'_> for AgeVisitor {
+            type Value = Age;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number of seconds or a duration such as \"14d\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Age, E> {
+                Ok(Age(Duration::from_secs(v)))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Age, E> {
+                u64::try_from(v).map(|v| Age(Duration::from_secs(v))).map_err(|_| E::custom(format!("invalid age {}, expected a positive duration", v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Age, E> {
+                let s = v.trim();
+                let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+                let secs = match unit.trim() {
+                    "" | "s" => 1,
+                    "m" => 60,
+                    "h" => 3600,
+                    "d" => 86400,
+                    _ => 0,
+                };
+                match n.parse::<u64>() {
+                    Ok(n) if secs > 0 => Ok(Age(Duration::from_secs(n.saturating_mul(secs)))),
+                    _ => Err(E::custom(format!("invalid age \"{}\", expected a number followed by s, m, h or d", v))),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(AgeVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Formats {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        let mut format = Format::Nano;
+        for name in names {
+            format |= match name.to_lowercase().as_str() {
+                "nano" => Format::Nano,
+                "date" => Format::Date,
+                "time" => Format::Time,
+                "microseconds" => Format::Microseconds,
+                "longfilename" => Format::LongFileName,
+                "shortfilename" => Format::ShortFileName,
+                "levelflag" => Format::LevelFlag,
+                "json" => Format::Json,
+                _ => return Err(de::Error::custom(format!("unknown format flag \"{}\", expected one of Nano, Date, Time, Microseconds, LongFileName, ShortFileName, LevelFlag, Json", name))),
+            };
+        }
+        Ok(Formats(format))
+    }
+}
+
+impl<'de> Deserialize<'de> for Directives {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_directives(&s).map(Directives).map_err(|e| de::Error::custom(format!("invalid directive \"{}\"", e)))
+    }
+}
@@ -14,11 +14,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use tokio::io::AsyncWriteExt;
 
-use crate::{asyncfile, syncfile, Format, CUTMODE, LEVEL, MODE};
+use crate::{asyncfile, syncfile, DiskGuard, Durability, Failover, Format, CODEC, CUTMODE, LEVEL, MODE, STARTUP};
 
 pub trait FileOption: Send + Sync {
     fn mode(&self) -> CUTMODE;
@@ -27,6 +33,253 @@ pub trait FileOption: Send + Sync {
     fn size(&self) -> u64;
     fn maxbackups(&self) -> u32;
     fn compress(&self) -> bool;
+
+    /// The settings below, which the file modes keep together; see `FileSettings`.
+    fn settings(&self) -> FileSettings {
+        FileSettings::default()
+    }
+
+    /// The codec of compressed backups, used when `compress` is true.
+    fn codec(&self) -> CODEC {
+        self.settings().codec
+    }
+
+    /// The compression level; 0 selects the codec's default.
+    fn compress_level(&self) -> u32 {
+        self.settings().compress_level
+    }
+
+    /// The maximum total size in bytes of all backups; 0 for no limit.
+    fn max_total_size(&self) -> u64 {
+        self.settings().max_total_size
+    }
+
+    /// The maximum age of a backup, going by its modification time; zero for no limit.
+    fn max_age(&self) -> Duration {
+        self.settings().max_age
+    }
+
+    /// The template backups are named after; `None` keeps `{stem}_{date}_{counter}.{ext}`.
+    fn backup_name(&self) -> Option<String> {
+        self.settings().backup_name
+    }
+
+    /// How often to check that the log file is still at its path, recreating it when it was deleted
+    /// or moved away; zero for never.
+    fn check_interval(&self) -> Duration {
+        self.settings().check_interval
+    }
+
+    /// Whether to write to files named like backups and keep `filename` as a symlink to the current one,
+    /// instead of renaming `filename` on rotation.
+    fn current_link(&self) -> bool {
+        self.settings().current_link
+    }
+
+    /// What to do with an existing log file when the handler is created.
+    fn startup(&self) -> STARTUP {
+        self.settings().startup
+    }
+
+    /// Whether several processes write to the file, taking turns through a lock file next to it.
+    fn multi_process(&self) -> bool {
+        self.settings().multi_process
+    }
+
+    /// When records are written out and synced to disk.
+    fn durability(&self) -> Durability {
+        self.settings().durability
+    }
+
+    /// Where records go while the log file cannot be written; None to report them as lost.
+    fn failover(&self) -> Option<Failover> {
+        self.settings().failover
+    }
+
+    /// What gives way when the volume of the log file runs short of space; None to fill it.
+    fn disk_guard(&self) -> Option<DiskGuard> {
+        self.settings().disk_guard
+    }
+}
+
+/// How a log file is compressed, pruned, named, opened and written, besides its name and when it rotates.
+/// `FileOptionType`, `FileTimeMode`, `FileSizeMode` and `FileMixedMode` each carry one and offer its builders.
+#[derive(Clone)]
+pub struct FileSettings {
+    codec: CODEC,                  //The codec of compressed log files
+    compress_level: u32,           //Compression level, 0 for the codec's default
+    max_total_size: u64,           //The maximum total size of old log files, 0 for no limit
+    max_age: Duration,             //The maximum age of old log files, zero for no limit
+    backup_name: Option<String>,   //The template old log files are named after
+    check_interval: Duration,      //How often the log file is checked to still be at its path, zero for never
+    current_link: bool,            //Whether the log file path is a symlink to the file being written
+    startup: STARTUP,              //What happens to an existing log file when the handler is created
+    multi_process: bool,           //Whether other processes write to the same log file
+    durability: Durability,        //When records are written out and synced to disk
+    failover: Option<Failover>,    //Where records go while the log file cannot be written
+    disk_guard: Option<DiskGuard>, //What gives way when the volume of the log file runs short of space
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        FileSettings { codec: CODEC::GZIP, compress_level: 0, max_total_size: 0, max_age: Duration::ZERO, backup_name: None, check_interval: Duration::ZERO, current_link: false, startup: STARTUP::APPEND, multi_process: false, durability: Durability::default(), failover: None, disk_guard: None }
+    }
+}
+
+impl FileSettings {
+    // Reads the settings through the getters, which an option may override one by one.
+    pub(crate) fn of(fo: &dyn FileOption) -> Self {
+        FileSettings {
+            codec: fo.codec(),
+            compress_level: fo.compress_level(),
+            max_total_size: fo.max_total_size(),
+            max_age: fo.max_age(),
+            backup_name: fo.backup_name(),
+            check_interval: fo.check_interval(),
+            current_link: fo.current_link(),
+            startup: fo.startup(),
+            multi_process: fo.multi_process(),
+            durability: fo.durability(),
+            failover: fo.failover(),
+            disk_guard: fo.disk_guard(),
+        }
+    }
+
+    /// Compresses old log files with `codec` at `level`, when compression is on; a level of 0 selects the codec's default.
+    pub fn with_compression(mut self, codec: CODEC, level: u32) -> Self {
+        self.codec = codec;
+        self.compress_level = level;
+        self
+    }
+
+    /// Deletes the oldest log files once they exceed `max_total_size` bytes in total or are older than `max_age`.
+    /// Both limits apply together with the backup count; 0 and `Duration::ZERO` mean no limit.
+    pub fn with_retention(mut self, max_total_size: u64, max_age: Duration) -> Self {
+        self.max_total_size = max_total_size;
+        self.max_age = max_age;
+        self
+    }
+
+    /// Names old log files after `template`, e.g. `"{stem}.{YYYY}-{MM}-{DD}T{hh}.{ext}"` or `"{stem}.{ext}.{counter}"`.
+    /// Placeholders are `{stem}`, `{ext}`, `{counter}`, `{date}` (as in the default names), `{YYYY}`, `{MM}`, `{DD}`, `{hh}` and `{mm}`;
+    /// without `{counter}`, further backups of the same period get `.1`, `.2`, ... appended. Compressed backups add the codec's extension.
+    pub fn with_backup_name(mut self, template: &str) -> Self {
+        self.backup_name = Some(template.to_string());
+        self
+    }
+
+    /// Checks every `interval` that the log file is still at its path, e.g. after it was deleted or
+    /// moved away, and recreates it if not. `Duration::ZERO` turns the check off.
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Writes straight to files named like backups, e.g. `app_20261018_1.log`, and keeps the log file path
+    /// as a symlink to the current one, switched atomically on rotation. Old files are compressed and
+    /// pruned as usual. Use `with_backup_name("{stem}_{date}.{ext}")` for names such as `app_20261018.log`.
+    pub fn with_current_link(mut self) -> Self {
+        self.current_link = true;
+        self
+    }
+
+    /// Sets what happens to an existing log file when the handler is created: `STARTUP::APPEND` continues it,
+    /// `STARTUP::ROTATE` rotates it first and `STARTUP::PERRUN` writes to a new file for every run.
+    pub fn with_startup(mut self, startup: STARTUP) -> Self {
+        self.startup = startup;
+        self
+    }
+
+    /// Lets several processes write to the same log file. Each write and rotation holds an advisory lock on
+    /// `.{name}.lock` next to the file, and rechecks the file's real size and whether another process has rotated it.
+    pub fn with_multi_process(mut self) -> Self {
+        self.multi_process = true;
+        self
+    }
+
+    /// Buffers records and syncs them to disk as `durability` says; see `Durability`.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Writes to a fallback after repeated write errors until the log file recovers; see `Failover`.
+    pub fn with_failover(mut self, failover: Failover) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    /// Prunes backups, then drops minor records, while the volume of the log file is short of space; see `DiskGuard`.
+    pub fn with_disk_guard(mut self, guard: DiskGuard) -> Self {
+        self.disk_guard = Some(guard);
+        self
+    }
+}
+
+// The builders of `FileSettings` for a file mode keeping one in `settings`.
+macro_rules! settings_builders {
+    () => {
+        /// Compresses old log files with `codec` at `level`; a level of 0 selects the codec's default.
+        pub fn with_compression(mut self, codec: CODEC, level: u32) -> Self {
+            self.compress = true;
+            self.settings = self.settings.with_compression(codec, level);
+            self
+        }
+
+        /// See `FileSettings::with_retention`.
+        pub fn with_retention(mut self, max_total_size: u64, max_age: Duration) -> Self {
+            self.settings = self.settings.with_retention(max_total_size, max_age);
+            self
+        }
+
+        /// See `FileSettings::with_backup_name`.
+        pub fn with_backup_name(mut self, template: &str) -> Self {
+            self.settings = self.settings.with_backup_name(template);
+            self
+        }
+
+        /// See `FileSettings::with_check_interval`.
+        pub fn with_check_interval(mut self, interval: Duration) -> Self {
+            self.settings = self.settings.with_check_interval(interval);
+            self
+        }
+
+        /// See `FileSettings::with_current_link`.
+        pub fn with_current_link(mut self) -> Self {
+            self.settings = self.settings.with_current_link();
+            self
+        }
+
+        /// See `FileSettings::with_startup`.
+        pub fn with_startup(mut self, startup: STARTUP) -> Self {
+            self.settings = self.settings.with_startup(startup);
+            self
+        }
+
+        /// See `FileSettings::with_multi_process`.
+        pub fn with_multi_process(mut self) -> Self {
+            self.settings = self.settings.with_multi_process();
+            self
+        }
+
+        /// See `FileSettings::with_durability`.
+        pub fn with_durability(mut self, durability: Durability) -> Self {
+            self.settings = self.settings.with_durability(durability);
+            self
+        }
+
+        /// See `FileSettings::with_failover`.
+        pub fn with_failover(mut self, failover: Failover) -> Self {
+            self.settings = self.settings.with_failover(failover);
+            self
+        }
+
+        /// See `FileSettings::with_disk_guard`.
+        pub fn with_disk_guard(mut self, guard: DiskGuard) -> Self {
+            self.settings = self.settings.with_disk_guard(guard);
+            self
+        }
+    };
 }
 
 pub struct FileOptionType {
@@ -36,12 +289,15 @@ pub struct FileOptionType {
     pub size: u64,
     pub maxbackups: u32,
     pub compress: bool,
+    pub settings: FileSettings,
 }
 
 impl FileOptionType {
     pub fn new(mode: CUTMODE, timemode: MODE, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> Self {
-        FileOptionType { mode: mode, timemode: timemode, filename: filename.to_string(), size: maxsize, maxbackups, compress }
+        FileOptionType { mode: mode, timemode: timemode, filename: filename.to_string(), size: maxsize, maxbackups, compress, settings: FileSettings::default() }
     }
+
+    settings_builders!();
 }
 
 impl FileOption for FileOptionType {
@@ -68,19 +324,26 @@ impl FileOption for FileOptionType {
     fn compress(&self) -> bool {
         self.compress
     }
+
+    fn settings(&self) -> FileSettings {
+        self.settings.clone()
+    }
 }
 
 pub struct FileTimeMode {
-    filename: String, //Log file path
-    mode: MODE,       //Scroll backup files by time, hour, day, month
-    max_backups: u32, //The maximum number of old log files that can be retained
-    compress: bool,   //Whether to compress old log files
+    filename: String,       //Log file path
+    mode: MODE,             //Scroll backup files by time, hour, day, month
+    max_backups: u32,       //The maximum number of old log files that can be retained
+    compress: bool,         //Whether to compress old log files
+    settings: FileSettings, //Compression codec, retention, naming and writing of the log files
 }
 
 impl FileTimeMode {
     pub fn new(filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> Self {
-        FileTimeMode { filename: filename.to_string(), mode, max_backups: maxbackups, compress }
+        FileTimeMode { filename: filename.to_string(), mode, max_backups: maxbackups, compress, settings: FileSettings::default() }
     }
+
+    settings_builders!();
 }
 
 impl FileOption for FileTimeMode {
@@ -107,13 +370,18 @@ impl FileOption for FileTimeMode {
     fn compress(&self) -> bool {
         return self.compress;
     }
+
+    fn settings(&self) -> FileSettings {
+        self.settings.clone()
+    }
 }
 
 pub struct FileSizeMode {
-    filename: String, //Log file path
-    max_size: u64,    //Maximum size for each log file to be saved
-    max_backups: u32, //The maximum number of old log files that can be retained
-    compress: bool,   //Whether to compress old log files
+    filename: String,       //Log file path
+    max_size: u64,          //Maximum size for each log file to be saved
+    max_backups: u32,       //The maximum number of old log files that can be retained
+    compress: bool,         //Whether to compress old log files
+    settings: FileSettings, //Compression codec, retention, naming and writing of the log files
 }
 
 impl FileOption for FileSizeMode {
@@ -140,20 +408,27 @@ impl FileOption for FileSizeMode {
     fn compress(&self) -> bool {
         return self.compress;
     }
+
+    fn settings(&self) -> FileSettings {
+        self.settings.clone()
+    }
 }
 
 impl FileSizeMode {
     pub fn new(filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> Self {
-        FileSizeMode { filename: filename.to_string(), max_size: maxsize, max_backups: maxbackups, compress }
+        FileSizeMode { filename: filename.to_string(), max_size: maxsize, max_backups: maxbackups, compress, settings: FileSettings::default() }
     }
+
+    settings_builders!();
 }
 
 pub struct FileMixedMode {
-    filename: String, //Log file path
-    max_size: u64,    //Maximum size for each log file to be saved
-    mode: MODE,       ////Scroll backup files by time, hour, day, month
-    max_backups: u32, //The maximum number of old log files that can be retained
-    compress: bool,   //Whether to compress old log files
+    filename: String,       //Log file path
+    max_size: u64,          //Maximum size for each log file to be saved
+    mode: MODE,             ////Scroll backup files by time, hour, day, month
+    max_backups: u32,       //The maximum number of old log files that can be retained
+    compress: bool,         //Whether to compress old log files
+    settings: FileSettings, //Compression codec, retention, naming and writing of the log files
 }
 
 impl FileOption for FileMixedMode {
@@ -180,12 +455,18 @@ impl FileOption for FileMixedMode {
     fn compress(&self) -> bool {
         return self.compress;
     }
+
+    fn settings(&self) -> FileSettings {
+        self.settings.clone()
+    }
 }
 
 impl FileMixedMode {
     pub fn new(filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> Self {
-        FileMixedMode { filename: filename.to_string(), max_size: maxsize, mode: mode, max_backups: maxbackups, compress }
+        FileMixedMode { filename: filename.to_string(), max_size: maxsize, mode: mode, max_backups: maxbackups, compress, settings: FileSettings::default() }
     }
+
+    settings_builders!();
 }
 
 pub struct FmtHandler {
@@ -253,49 +534,218 @@ impl FmtHandler {
     }
 }
 
+/// A destination for formatted log lines.
+///
+/// The console and the rotating `syncfile::FileHandler` are built-in sinks;
+/// any other destination can be attached with `LogOption::with_sink`.
+pub trait Sink: Send {
+    fn write(&mut self, level: LEVEL, s: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>>;
+
+/// The async variant of [`Sink`], used by `Async::Logger`.
+///
+/// The console and the rotating `asyncfile::FileHandler` are built-in sinks;
+/// any other destination can be attached with `LogOption::with_async_sink`.
+pub trait AsyncSink: Send {
+    fn write<'a>(&'a mut self, level: LEVEL, s: &'a str) -> SinkFuture<'a>;
+
+    fn flush(&mut self) -> SinkFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+pub type SinkRef = Arc<Mutex<dyn Sink>>;
+
+pub type AsyncSinkRef = Arc<tokio::sync::Mutex<dyn AsyncSink>>;
+
+/// Writes `s` to every sink, returning the last error if any of them failed.
+pub fn write_sinks(sinks: &[SinkRef], level: LEVEL, s: &str) -> io::Result<()> {
+    let mut r = Ok(());
+    for sink in sinks {
+        match sink.lock() {
+            Ok(mut sk) => {
+                if let Err(e) = sk.write(level, s) {
+                    r = Err(e);
+                }
+            }
+            Err(_) => r = Err(io::Error::other("sink lock poisoned")),
+        }
+    }
+    r
+}
+
+pub async fn async_write_sinks(sinks: &[AsyncSinkRef], level: LEVEL, s: &str) -> io::Result<()> {
+    let mut r = Ok(());
+    for sink in sinks {
+        if let Err(e) = sink.lock().await.write(level, s).await {
+            r = Err(e);
+        }
+    }
+    r
+}
+
+/// Flushes every sink, returning the last error if any of them failed.
+pub fn flush_sinks(sinks: &[SinkRef]) -> io::Result<()> {
+    let mut r = Ok(());
+    for sink in sinks {
+        match sink.lock() {
+            Ok(mut sk) => {
+                if let Err(e) = sk.flush() {
+                    r = Err(e);
+                }
+            }
+            Err(_) => r = Err(io::Error::other("sink lock poisoned")),
+        }
+    }
+    r
+}
+
+pub async fn async_flush_sinks(sinks: &[AsyncSinkRef]) -> io::Result<()> {
+    let mut r = Ok(());
+    for sink in sinks {
+        if let Err(e) = sink.lock().await.flush().await {
+            r = Err(e);
+        }
+    }
+    r
+}
+
 pub struct FHandler {
     file_handler: Option<syncfile::FileHandler>,
     async_file_handler: Option<asyncfile::FileHandler>,
-    async_console: Option<Console>,
+    async_console: Option<ConsoleSink>,
+    sinks: Vec<SinkRef>,
+    async_sinks: Vec<AsyncSinkRef>,
 }
 
 impl FHandler {
     pub fn new() -> Self {
-        FHandler { file_handler: None, async_file_handler: None, async_console: None }
+        FHandler { file_handler: None, async_file_handler: None, async_console: None, sinks: Vec::new(), async_sinks: Vec::new() }
     }
 
     pub fn new_with_handler(fh: Box<syncfile::FileHandler>) -> Self {
-        FHandler { file_handler: Some(*fh), async_file_handler: None, async_console: None }
+        FHandler { file_handler: Some(*fh), async_file_handler: None, async_console: None, sinks: Vec::new(), async_sinks: Vec::new() }
     }
 
     pub fn new_with_asynchandler(fh: Box<asyncfile::FileHandler>) -> Self {
-        FHandler { file_handler: None, async_file_handler: Some(*fh), async_console: Some(Console::new()) }
+        FHandler { file_handler: None, async_file_handler: Some(*fh), async_console: Some(ConsoleSink::new()), sinks: Vec::new(), async_sinks: Vec::new() }
     }
 
-    pub fn print(&mut self, is_console: bool, console: &str, s: &str) -> io::Result<()> {
+    pub fn print(&mut self, level: LEVEL, is_console: bool, console: &str, s: &str) -> io::Result<()> {
         if is_console {
-            print!("{}", if console.is_empty() { s } else { console });
+            let _ = Sink::write(&mut ConsoleSink::new(), level, if console.is_empty() { s } else { console });
         }
+        let mut r = Ok(());
         if let Some(f) = self.file_handler.as_mut() {
-            f.write(s.as_bytes())?;
+            r = Sink::write(f, level, s);
         }
-        Ok(())
+        if !self.sinks.is_empty() {
+            if let Err(e) = write_sinks(&self.sinks, level, s) {
+                r = Err(e);
+            }
+        }
+        r
     }
 
-    pub async fn async_print(&mut self, is_console: bool, console: &str, s: &str) -> io::Result<()> {
+    pub async fn async_print(&mut self, level: LEVEL, is_console: bool, console: &str, s: &str) -> io::Result<()> {
         if is_console {
-            if self.async_console.is_none() {
-                let cs = Console::new();
-                let _ = cs.async_print(if console.is_empty() { s } else { console }).await;
-                self.async_console = Some(cs)
-            } else if let Some(c) = self.async_console.as_mut() {
-                let _ = c.async_print(if console.is_empty() { s } else { console }).await;
+            let c = self.async_console.get_or_insert_with(ConsoleSink::new);
+            let _ = AsyncSink::write(c, level, if console.is_empty() { s } else { console }).await;
+        }
+        let mut r = Ok(());
+        if let Some(f) = self.async_file_handler.as_mut() {
+            r = AsyncSink::write(f, level, s).await;
+        }
+        if !self.async_sinks.is_empty() {
+            if let Err(e) = async_write_sinks(&self.async_sinks, level, s).await {
+                r = Err(e);
             }
         }
+        r
+    }
+
+    /// Flushes the console, syncs the log file and flushes the attached sinks.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut r = Sink::flush(&mut ConsoleSink::new());
+        if let Some(f) = self.file_handler.as_mut() {
+            if let Err(e) = Sink::flush(f) {
+                r = Err(e);
+            }
+        }
+        if let Err(e) = flush_sinks(&self.sinks) {
+            r = Err(e);
+        }
+        r
+    }
+
+    pub async fn async_flush(&mut self) -> io::Result<()> {
+        let c = self.async_console.get_or_insert_with(ConsoleSink::new);
+        let mut r = AsyncSink::flush(c).await;
         if let Some(f) = self.async_file_handler.as_mut() {
-            f.write(s.as_bytes()).await?;
+            if let Err(e) = AsyncSink::flush(f).await {
+                r = Err(e);
+            }
         }
-        Ok(())
+        if let Err(e) = async_flush_sinks(&self.async_sinks).await {
+            r = Err(e);
+        }
+        r
+    }
+
+    /// Reopens the log file at its configured path; see `syncfile::FileHandler::reopen`.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        match self.file_handler.as_mut() {
+            Some(f) => f.reopen(),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn async_reopen(&mut self) -> io::Result<()> {
+        match self.async_file_handler.as_mut() {
+            Some(f) => f.reopen().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a buffer whose flush interval has passed; see `syncfile::FileHandler::write_due`.
+    pub(crate) fn write_due(&mut self) -> Option<Duration> {
+        self.file_handler.as_mut().and_then(|f| f.write_due())
+    }
+
+    pub(crate) async fn async_write_due(&mut self) -> Option<Duration> {
+        match self.async_file_handler.as_mut() {
+            Some(f) => f.write_due().await,
+            None => None,
+        }
+    }
+
+    /// Rotates the log file now; see `syncfile::FileHandler::rotate_now`.
+    pub fn rotate_now(&mut self) -> io::Result<()> {
+        match self.file_handler.as_mut() {
+            Some(f) => f.rotate_now(),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn async_rotate_now(&mut self) -> io::Result<()> {
+        match self.async_file_handler.as_mut() {
+            Some(f) => f.rotate_now().await,
+            None => Ok(()),
+        }
+    }
+
+    pub fn add_sink(&mut self, sink: SinkRef) {
+        self.sinks.push(sink);
+    }
+
+    pub fn add_async_sink(&mut self, sink: AsyncSinkRef) {
+        self.async_sinks.push(sink);
     }
 
     pub async fn async_console(&self, s: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -314,15 +764,57 @@ impl FHandler {
     }
 }
 
-struct Console {}
-impl Console {
+/// The built-in console sink, writing to stdout.
+#[derive(Default)]
+pub struct ConsoleSink;
+
+impl ConsoleSink {
     pub fn new() -> Self {
-        Console {}
+        ConsoleSink
     }
-    pub async fn async_print(&self, s: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut stdout = tokio::io::stdout();
-        tokio::io::stdout().write_all(s.as_bytes()).await?;
-        stdout.flush().await?;
+}
+
+impl Sink for ConsoleSink {
+    fn write(&mut self, _: LEVEL, s: &str) -> io::Result<()> {
+        print!("{}", s);
         Ok(())
     }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut io::stdout())
+    }
+}
+
+impl AsyncSink for ConsoleSink {
+    fn write<'a>(&'a mut self, _: LEVEL, s: &'a str) -> SinkFuture<'a> {
+        Box::pin(async move {
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(s.as_bytes()).await?;
+            stdout.flush().await
+        })
+    }
+
+    fn flush(&mut self) -> SinkFuture<'_> {
+        Box::pin(async { tokio::io::stdout().flush().await })
+    }
+}
+
+impl Sink for syncfile::FileHandler {
+    fn write(&mut self, level: LEVEL, s: &str) -> io::Result<()> {
+        syncfile::FileHandler::write_level(self, level, s.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        syncfile::FileHandler::flush(self)
+    }
+}
+
+impl AsyncSink for asyncfile::FileHandler {
+    fn write<'a>(&'a mut self, level: LEVEL, s: &'a str) -> SinkFuture<'a> {
+        Box::pin(asyncfile::FileHandler::write_level(self, level, s.as_bytes()))
+    }
+
+    fn flush(&mut self) -> SinkFuture<'_> {
+        Box::pin(asyncfile::FileHandler::flush(self))
+    }
 }
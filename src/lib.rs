@@ -15,30 +15,37 @@
 // limitations under the License.
 
 use std::{
+    collections::HashMap,
     env,
     fmt::{self, Debug},
     fs::{self, File},
-    io::{self, BufReader, BufWriter, Read, Write},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
 };
 
-use chrono::{DateTime, Datelike, Local, NaiveDateTime, Timelike};
-use flate2::{
-    write::{GzEncoder, ZlibEncoder},
-    Compression,
-};
-use handle::FileOptionType;
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use flate2::{write::GzEncoder, Compression};
+use handle::{FileOptionType, FileSettings};
 use once_cell::sync::Lazy;
-use tokio::io::AsyncReadExt;
 #[allow(non_snake_case)]
 pub mod Async;
 pub mod asyncfile;
 pub mod asyncmulti;
+pub mod config;
 pub mod handle;
 mod mwrite;
+mod naming;
+pub mod schedule;
 pub mod sync;
 pub mod syncfile;
 pub mod syncmulti;
+mod queue;
 #[allow(non_snake_case)]
 mod threadPool;
 mod trie;
@@ -54,8 +61,17 @@ pub trait OptionTrait {
     fn get_formatter(&self) -> Option<String>;
     fn get_console(&self) -> Option<bool>;
     fn get_fileoption(&self) -> Option<Box<dyn handle::FileOption>>;
+
+    fn get_sinks(&self) -> Vec<handle::SinkRef> {
+        Vec::new()
+    }
+
+    fn get_async_sinks(&self) -> Vec<handle::AsyncSinkRef> {
+        Vec::new()
+    }
 }
 
+#[derive(Default)]
 pub struct LogOption {
     pub level: Option<LEVEL>,
     pub format: Option<u8>,
@@ -94,6 +110,16 @@ impl LogOption {
         self
     }
 
+    /// Attaches a sink that receives every line written by this option in `sync::Logger`.
+    pub fn with_sink(self, sink: impl handle::Sink + 'static) -> SinkOption {
+        SinkOption::from(self).with_sink(sink)
+    }
+
+    /// Attaches a sink that receives every line written by this option in `Async::Logger`.
+    pub fn with_async_sink(self, sink: impl handle::AsyncSink + 'static) -> SinkOption {
+        SinkOption::from(self).with_async_sink(sink)
+    }
+
     pub fn take(&mut self) -> Self {
         LogOption { level: self.level.take(), format: self.format.take(), formatter: self.formatter.take(), console: self.console.take(), fileoption: self.fileoption.take() }
     }
@@ -118,18 +144,84 @@ impl OptionTrait for LogOption {
 
     fn get_fileoption(&self) -> Option<Box<dyn handle::FileOption>> {
         if let Some(fo) = &self.fileoption {
-            return Some(Box::new(FileOptionType { mode: fo.mode(), timemode: fo.timemode(), filename: fo.filename().clone(), size: fo.size(), maxbackups: fo.maxbackups(), compress: fo.compress() }));
+            return Some(Box::new(FileOptionType { mode: fo.mode(), timemode: fo.timemode(), filename: fo.filename().clone(), size: fo.size(), maxbackups: fo.maxbackups(), compress: fo.compress(), settings: FileSettings::of(fo.as_ref()) }));
         }
         None
     }
+
 }
 
-#[derive(Clone)]
+/// A `LogOption` with extra destinations, accepted wherever a `LogOption` is.
+/// Built with `LogOption::with_sink` and `LogOption::with_async_sink`; without a file of its own,
+/// a module or level option also writes to the logger's file and sinks.
+#[derive(Default)]
+pub struct SinkOption {
+    option: LogOption,
+    sinks: Vec<handle::SinkRef>,
+    async_sinks: Vec<handle::AsyncSinkRef>,
+}
+
+impl SinkOption {
+    pub fn with_sink(mut self, sink: impl handle::Sink + 'static) -> Self {
+        self.sinks.push(std::sync::Arc::new(std::sync::Mutex::new(sink)));
+        self
+    }
+
+    pub fn with_async_sink(mut self, sink: impl handle::AsyncSink + 'static) -> Self {
+        self.async_sinks.push(std::sync::Arc::new(tokio::sync::Mutex::new(sink)));
+        self
+    }
+
+    // Splits the option from its sinks for the loggers.
+    pub(crate) fn into_parts(self) -> (LogOption, Vec<handle::SinkRef>, Vec<handle::AsyncSinkRef>) {
+        (self.option, self.sinks, self.async_sinks)
+    }
+}
+
+impl From<LogOption> for SinkOption {
+    fn from(option: LogOption) -> Self {
+        SinkOption { option, sinks: Vec::new(), async_sinks: Vec::new() }
+    }
+}
+
+impl OptionTrait for SinkOption {
+    fn get_level(&self) -> Option<LEVEL> {
+        self.option.get_level()
+    }
+
+    fn get_format(&self) -> Option<u8> {
+        self.option.get_format()
+    }
+
+    fn get_formatter(&self) -> Option<String> {
+        self.option.get_formatter()
+    }
+
+    fn get_console(&self) -> Option<bool> {
+        self.option.get_console()
+    }
+
+    fn get_fileoption(&self) -> Option<Box<dyn handle::FileOption>> {
+        self.option.get_fileoption()
+    }
+
+    fn get_sinks(&self) -> Vec<handle::SinkRef> {
+        self.sinks.clone()
+    }
+
+    fn get_async_sinks(&self) -> Vec<handle::AsyncSinkRef> {
+        self.async_sinks.clone()
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct LogOptionConst {
     pub level: Option<LEVEL>,
     pub format: Option<u8>,
     pub formatter: Option<String>,
     pub console: Option<bool>,
+    pub sinks: Vec<handle::SinkRef>,
+    pub async_sinks: Vec<handle::AsyncSinkRef>,
 }
 
 #[derive(Clone)]
@@ -139,6 +231,111 @@ pub struct LogContext {
     pub filename: String,
     pub line: u32,
     pub modname: String,
+    pub fields: Vec<(String, FieldValue)>,
+    pub target: String,
+}
+
+/// A typed value attached to a log record with the `key = value` macro syntax.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Str(String),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Str(v) => f.write_str(v),
+            FieldValue::I64(v) => write!(f, "{}", v),
+            FieldValue::U64(v) => write!(f, "{}", v),
+            FieldValue::F64(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Converts a macro field value into a [`FieldValue`].
+///
+/// Implement it for your own types, or capture them with `key:% = value` (Display)
+/// or `key:? = value` (Debug).
+pub trait ToFieldValue {
+    fn to_field_value(&self) -> FieldValue;
+}
+
+macro_rules! impl_field_value {
+    ($variant:ident, $as:ty, $($t:ty),*) => {
+        $(impl ToFieldValue for $t {
+            fn to_field_value(&self) -> FieldValue {
+                FieldValue::$variant(*self as $as)
+            }
+        })*
+    };
+}
+
+impl_field_value!(I64, i64, i8, i16, i32, i64, isize);
+impl_field_value!(U64, u64, u8, u16, u32, u64, usize);
+impl_field_value!(F64, f64, f32, f64);
+
+impl ToFieldValue for bool {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Bool(*self)
+    }
+}
+
+impl ToFieldValue for char {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Str(self.to_string())
+    }
+}
+
+impl ToFieldValue for str {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Str(self.to_string())
+    }
+}
+
+impl ToFieldValue for String {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Str(self.clone())
+    }
+}
+
+impl ToFieldValue for FieldValue {
+    fn to_field_value(&self) -> FieldValue {
+        self.clone()
+    }
+}
+
+impl<T: ToFieldValue + ?Sized> ToFieldValue for &T {
+    fn to_field_value(&self) -> FieldValue {
+        (**self).to_field_value()
+    }
+}
+
+impl<T: ToFieldValue> ToFieldValue for Option<T> {
+    fn to_field_value(&self) -> FieldValue {
+        match self {
+            Some(v) => v.to_field_value(),
+            None => FieldValue::Str(String::new()),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_value {
+    (% $value:expr) => {
+        $crate::FieldValue::Str(format!("{}", $value))
+    };
+    (? $value:expr) => {
+        $crate::FieldValue::Str(format!("{:?}", $value))
+    };
+    ($value:expr) => {
+        $crate::ToFieldValue::to_field_value(&$value)
+    };
 }
 
 pub struct LevelOption {
@@ -177,6 +374,7 @@ pub mod Format {
     pub const LongFileName: u8 = 8;
     pub const ShortFileName: u8 = 16;
     pub const LevelFlag: u8 = 32;
+    pub const Json: u8 = 64;
 }
 
 #[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
@@ -193,21 +391,26 @@ impl ErrCode {
 // const DEFAULT_FORMATTER: &str = "{level}{time} {file}:{message}\n";
 // const MWRITE: Lazy<mwrite::MWrite> = Lazy::new(|| mwrite::MWrite::new());
 
-pub const LOG: Lazy<sync::Log> = Lazy::new(|| sync::Log::new());
+pub static LOG: Lazy<sync::Log> = Lazy::new(|| sync::Log::new());
 
 static TKLOG2SYNCLOG: sync::Log = sync::Log;
 
-pub const ASYNC_LOG: Lazy<Async::Log> = Lazy::new(|| Async::Log::new());
+pub static ASYNC_LOG: Lazy<Async::Log> = Lazy::new(|| Async::Log::new());
 
 static TKLOG2ASYNC_LOG: Async::Log = Async::Log;
 
+/// The loggers behind `LOG` and `ASYNC_LOG`.
+///
+/// Both are guarded by a std `Mutex`; the async logger never holds it across an
+/// `.await` in caller tasks, so futures using the async macros stay `Send`.
 #[allow(non_upper_case_globals)]
 pub mod tklog {
     use crate::{sync, Async};
     use once_cell::sync::Lazy;
+    use std::sync::Mutex;
 
-    pub static mut synclog: Lazy<sync::Logger> = Lazy::new(|| sync::Logger::new());
-    pub static mut asynclog: Lazy<Async::Logger> = Lazy::new(|| Async::Logger::new());
+    pub static synclog: Lazy<Mutex<sync::Logger>> = Lazy::new(|| Mutex::new(sync::Logger::new()));
+    pub static asynclog: Lazy<Mutex<Async::Logger>> = Lazy::new(|| Mutex::new(Async::Logger::new()));
 }
 
 #[derive(PartialEq, PartialOrd)]
@@ -216,7 +419,20 @@ pub enum PRINTMODE {
     PUNCTUAL,
 }
 
-#[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
+/// What a `PRINTMODE::DELAY` logger does with a record when its queue is full.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum OVERFLOW {
+    /// Wait until the writer has made room.
+    Block,
+    /// Discard the new record.
+    DropNewest,
+    /// Discard the oldest queued record to make room.
+    DropOldest,
+    /// Discard the new record if its level is below the given one, otherwise wait.
+    DropBelow(LEVEL),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum LEVEL {
     Trace = 1,
@@ -244,18 +460,65 @@ impl FromStr for LEVEL {
     }
 }
 
-fn env_level() -> LEVEL {
-    if let Ok(rust_log) = env::var("RUST_LOG") {
-        match LEVEL::from_str(&rust_log) {
-            Ok(level) => level,
-            Err(_) => {
-                println!("Warning: Unknown log level '{}', defaulting to Debug", rust_log);
-                LEVEL::Debug
-            }
+/// One entry of a `RUST_LOG` style directive list.
+///
+/// `warn` sets the default level (`module` is `None`), `my_app::db=trace` sets the level
+/// of a module and its submodules, and a bare module name such as `my_app` enables all of its levels.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Directive {
+    pub module: Option<String>,
+    pub level: LEVEL,
+}
+
+/// Parses a comma separated directive list such as `info,hyper=warn,my_app::db=trace`.
+///
+/// Empty entries are skipped. On failure the error is the first entry that is not a directive.
+///
+/// # Examples
+///
+/// ```
+/// let directives = tklog::parse_directives("info,hyper=warn").unwrap();
+/// assert_eq!(directives[0], tklog::Directive { module: None, level: tklog::LEVEL::Info });
+/// assert_eq!(directives[1].module.as_deref(), Some("hyper"));
+/// ```
+pub fn parse_directives(s: &str) -> Result<Vec<Directive>, String> {
+    let mut directives = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let directive = match part.split_once('=') {
+            Some((module, level)) => match LEVEL::from_str(level.trim()) {
+                Ok(level) if !module.trim().is_empty() => Directive { module: Some(module.trim().to_string()), level },
+                _ => return Err(part.to_string()),
+            },
+            None => match LEVEL::from_str(part) {
+                Ok(level) => Directive { module: None, level },
+                Err(_) if part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':' || c == '*') => Directive { module: Some(part.to_string()), level: LEVEL::Trace },
+                Err(_) => return Err(part.to_string()),
+            },
+        };
+        directives.push(directive);
+    }
+    Ok(directives)
+}
+
+// RUST_LOG is read once; invalid entries are reported on stderr, never stdout, and skipped.
+static ENV_DIRECTIVES: Lazy<Vec<Directive>> = Lazy::new(|| {
+    let rust_log = env::var("RUST_LOG").unwrap_or_default();
+    let mut directives = Vec::new();
+    for part in rust_log.split(',') {
+        match parse_directives(part) {
+            Ok(d) => directives.extend(d),
+            Err(e) => eprintln!("tklog: unknown log directive '{}' in RUST_LOG, ignored", e),
         }
-    } else {
-        LEVEL::Debug
     }
+    directives
+});
+
+fn env_level() -> LEVEL {
+    ENV_DIRECTIVES.iter().rev().find(|d| d.module.is_none()).map_or(LEVEL::Debug, |d| d.level)
+}
+
+fn env_mod_directives() -> impl Iterator<Item = &'static Directive> {
+    ENV_DIRECTIVES.iter().filter(|d| d.module.is_some())
 }
 
 pub enum COLUMN {
@@ -266,11 +529,19 @@ pub enum COLUMN {
     MESSAGE,
 }
 
-#[derive(Copy, Clone)]
+pub use chrono::Weekday;
+
+/// The time schedule on which `CUTMODE::TIME` and `CUTMODE::MIXED` rotate log files.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MODE {
     HOUR,
     DAY,
     MONTH,
+    MINUTE,
+    /// Weekly, starting on the given day.
+    WEEK(Weekday),
+    /// Whenever the cron-like schedule matches.
+    CRON(schedule::Schedule),
 }
 
 #[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
@@ -280,6 +551,507 @@ pub enum CUTMODE {
     MIXED,
 }
 
+/// What a file handler does with the log file it finds when it is created.
+/// Only the first handler of a path in a process applies it; those rebuilt by a config reload carry on.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum STARTUP {
+    /// Continue writing to the existing file.
+    APPEND,
+    /// Rotate a non-empty existing file first, so every run starts a fresh file.
+    ROTATE,
+    /// Write to a file of this run, named after the start time and process ID, e.g. `app_20261018134501_4242.log`.
+    PERRUN,
+}
+
+/// When a file handler writes records out and syncs them to disk.
+/// The default writes every record at once and leaves syncing to the operating system.
+/// The sync file handler writes what it still buffers when it is dropped; the async one cannot,
+/// so flush an async logger before the runtime shuts down.
+///
+/// ```
+/// use std::time::Duration;
+/// use tklog::{Durability, LEVEL};
+///
+/// // batch up to 64KB for at most a second, but sync errors to disk right away
+/// let batched = Durability::buffered(64 * 1024, Duration::from_secs(1)).with_sync_level(LEVEL::Error);
+/// // sync every record before the write returns, for audit logs
+/// let audit = Durability::immediate();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Durability {
+    /// Records are collected in memory up to this many bytes; 0 writes every record at once.
+    /// Records the file does not take stay buffered for the next write, up to four times this size.
+    pub buffer_size: usize,
+    /// Buffered records are written once the oldest has waited this long; zero for no limit.
+    /// The writer thread of `LOG` and `ASYNC_LOG` also checks it while no records arrive;
+    /// a standalone logger checks it only as records arrive, so flush it before a quiet period.
+    pub flush_interval: Duration,
+    /// Syncs the file to disk after this many records; 0 for never.
+    pub sync_every: u32,
+    /// Syncs the file to disk after a record of this level or above.
+    pub sync_level: Option<LEVEL>,
+}
+
+impl Durability {
+    /// Collects records up to `size` bytes, or for at most `interval` when it is not zero.
+    pub fn buffered(size: usize, interval: Duration) -> Self {
+        Durability { buffer_size: size, flush_interval: interval, ..Default::default() }
+    }
+
+    /// Writes and syncs every record before returning.
+    pub fn immediate() -> Self {
+        Durability { sync_every: 1, ..Default::default() }
+    }
+
+    pub fn with_sync_every(mut self, records: u32) -> Self {
+        self.sync_every = records;
+        self
+    }
+
+    pub fn with_sync_level(mut self, level: LEVEL) -> Self {
+        self.sync_level = Some(level);
+        self
+    }
+
+    // Trims a buffer the file did not take to four times the buffer size, dropping the oldest records,
+    // so that a file which keeps failing does not hold every record in memory.
+    fn trim_unwritten(&self, buffer: &mut Vec<u8>) {
+        let limit = 4 * self.buffer_size;
+        if buffer.len() > limit {
+            let excess = buffer.len() - limit;
+            let cut = buffer[excess..].iter().position(|&b| b == b'\n').map_or(excess, |i| excess + i + 1);
+            buffer.drain(..cut);
+        }
+    }
+
+    // Whether the buffer is due to be written, holding `len` bytes since `since`.
+    fn buffer_due(&self, len: usize, since: std::time::Instant) -> bool {
+        len >= self.buffer_size || (!self.flush_interval.is_zero() && since.elapsed() >= self.flush_interval)
+    }
+
+    // How long until a buffer holding records since `since` is due by the flush interval;
+    // None when there is no interval to wait for.
+    fn flush_wait(&self, buffered: bool, since: std::time::Instant) -> Option<Duration> {
+        if self.buffer_size == 0 || self.flush_interval.is_zero() {
+            return None;
+        }
+        Some(if buffered { self.flush_interval.saturating_sub(since.elapsed()) } else { self.flush_interval })
+    }
+
+    // Whether to sync after a record of `level`, the `unsynced`th since the last sync.
+    fn sync_due(&self, level: Option<LEVEL>, unsynced: u32) -> bool {
+        (self.sync_every > 0 && unsynced >= self.sync_every) || matches!((level, self.sync_level), (Some(l), Some(min)) if l >= min)
+    }
+}
+
+/// Where a file handler writes while its log file cannot be written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FALLBACK {
+    /// Another file, e.g. on a different disk. Records go to stderr when it fails too.
+    FILE(String),
+    STDERR,
+}
+
+impl fmt::Display for FALLBACK {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FALLBACK::FILE(path) => f.write_str(path),
+            FALLBACK::STDERR => f.write_str("stderr"),
+        }
+    }
+}
+
+/// Moves a file handler to its fallback after repeated write errors, such as a full disk.
+/// The log file is retried with backoff; once it takes writes again, a marker record noting
+/// the gap is written to it and records return to it.
+///
+/// ```
+/// use std::time::Duration;
+/// use tklog::{Failover, FALLBACK};
+///
+/// let failover = Failover::new(FALLBACK::FILE("/tmp/app.log".to_string())).with_threshold(5).with_retry(Duration::from_secs(2), Duration::from_secs(300));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failover {
+    pub fallback: FALLBACK,
+    /// Consecutive failed writes before switching; the records that failed before are lost.
+    pub threshold: u32,
+    /// The wait before the first retry of the log file, doubled after each failed retry.
+    pub retry: Duration,
+    /// The longest wait between retries.
+    pub max_retry: Duration,
+}
+
+impl Failover {
+    /// Switches after 3 failed writes and retries after 1 second, backing off to 1 minute.
+    pub fn new(fallback: FALLBACK) -> Self {
+        Failover { fallback, threshold: 3, retry: Duration::from_secs(1), max_retry: Duration::from_secs(60) }
+    }
+
+    pub fn with_threshold(mut self, failures: u32) -> Self {
+        self.threshold = failures.max(1);
+        self
+    }
+
+    pub fn with_retry(mut self, first: Duration, max: Duration) -> Self {
+        self.retry = first;
+        self.max_retry = max.max(first);
+        self
+    }
+}
+
+// A spell of writing to the fallback while the log file cannot be written.
+pub(crate) struct Outage {
+    since: u64,
+    records: u64,
+    backoff: Duration,
+    retry_at: std::time::Instant,
+}
+
+impl Outage {
+    pub fn new(failover: &Failover) -> Self {
+        Outage { since: timesec(), records: 0, backoff: failover.retry, retry_at: std::time::Instant::now() + failover.retry }
+    }
+
+    pub fn retry_due(&self) -> bool {
+        std::time::Instant::now() >= self.retry_at
+    }
+
+    pub fn retry_failed(&mut self, failover: &Failover) {
+        self.backoff = (self.backoff * 2).min(failover.max_retry);
+        self.retry_at = std::time::Instant::now() + self.backoff;
+    }
+
+    pub fn diverted(&mut self) {
+        self.records += 1;
+    }
+
+    // The record written to the log file when it recovers.
+    pub fn marker(&self, file: &str, fallback: &FALLBACK) -> String {
+        let time = |secs: u64| wall_time(secs).format("%Y-%m-%d %H:%M:%S");
+        let records = if self.records == 1 { "record" } else { "records" };
+        format!("tklog: {} could not be written from {} to {}; {} {} went to {}\n", file, time(self.since), time(timesec()), self.records, records, fallback)
+    }
+}
+
+/// Keeps a log file from filling its volume and breaking the other services on the host.
+/// While less than `min_free` bytes are free, the oldest backups are deleted whatever the retention
+/// limits; once none are left, records below `level`, and raw writes without a level, are
+/// dropped until space is freed again.
+/// Free space is looked up with statvfs, so the guard does nothing outside Unix.
+///
+/// ```
+/// use tklog::{DiskGuard, LEVEL};
+///
+/// // keep 512MB free, and only write warnings and above once the backups are gone
+/// let guard = DiskGuard::new(512 << 20).with_level(LEVEL::Warn);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DiskGuard {
+    /// The bytes to keep free for unprivileged users of the volume.
+    pub min_free: u64,
+    /// The lowest level still written while space is short.
+    pub level: LEVEL,
+    /// How often the free space is looked up.
+    pub check_interval: Duration,
+}
+
+impl DiskGuard {
+    /// Keeps errors and fatals while space is short, and looks it up at most once a second.
+    pub fn new(min_free: u64) -> Self {
+        DiskGuard { min_free, level: LEVEL::Error, check_interval: Duration::from_secs(1) }
+    }
+
+    pub fn with_level(mut self, level: LEVEL) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    fn check_due(&self, checked_at: Option<std::time::Instant>) -> bool {
+        checked_at.is_none_or(|t| t.elapsed() >= self.check_interval)
+    }
+
+    // The report made when pruning could not free enough space.
+    fn shortage(&self, free: u64) -> io::Error {
+        io::Error::new(io::ErrorKind::StorageFull, format!("{} bytes free of the {} to keep; records below {:?} are dropped", free, self.min_free, self.level))
+    }
+}
+
+/// The bytes unprivileged users may still write to the volume holding `path`.
+#[cfg(unix)]
+fn free_space(path: &Path) -> io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // the field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn free_space(_: &Path) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "free space is only looked up on Unix"))
+}
+
+/// What a logger failed to do, as passed to its error handler and counted in `ErrorStats`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FAILURE {
+    /// Opening a log file, e.g. in `set_cutmode_by_size` when the directory is not writable.
+    OPEN,
+    /// Writing a record to a log file or a sink.
+    WRITE,
+    /// Rotating the log file, or reopening it after it was moved away.
+    ROTATE,
+    /// Compressing an old log file.
+    COMPRESS,
+    /// Deleting old log files under the retention limits.
+    RETENTION,
+    /// Keeping the free space a `DiskGuard` asks for, so that records are being dropped.
+    SPACE,
+}
+
+/// An error the logger met where there was no caller to return it to.
+#[derive(Debug)]
+pub struct LogError {
+    pub kind: FAILURE,
+    /// The file concerned; empty for a sink.
+    pub file: String,
+    pub error: io::Error,
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.kind {
+            FAILURE::OPEN => "open",
+            FAILURE::WRITE => "write",
+            FAILURE::ROTATE => "rotate",
+            FAILURE::COMPRESS => "compress",
+            FAILURE::RETENTION => "prune old files of",
+            FAILURE::SPACE => "keep free space for",
+        };
+        match self.file.as_str() {
+            "" => write!(f, "tklog failed to {} a sink: {}", action, self.error),
+            file => write!(f, "tklog failed to {} {}: {}", action, file, self.error),
+        }
+    }
+}
+
+/// The errors a logger has met since it was created, for health checks:
+/// a growing `write` count means records are being lost.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ErrorStats {
+    pub open: u64,
+    pub write: u64,
+    pub rotate: u64,
+    pub compress: u64,
+    pub retention: u64,
+    /// Times a `DiskGuard` started dropping records.
+    pub space: u64,
+    /// Records a `DiskGuard` dropped; they are not counted in `total`.
+    pub dropped: u64,
+    /// When the latest error happened, and its message.
+    pub last: Option<(SystemTime, String)>,
+}
+
+impl ErrorStats {
+    pub fn total(&self) -> u64 {
+        self.open + self.write + self.rotate + self.compress + self.retention + self.space
+    }
+}
+
+pub(crate) type ErrorHandler = Arc<dyn Fn(&LogError) + Send + Sync>;
+
+/// Counts the errors of one logger and passes them to its error handler.
+/// File handlers hold a clone, so errors from background compression reach the logger too.
+#[derive(Clone, Default)]
+pub(crate) struct Reporter(Arc<ReporterState>);
+
+#[derive(Default)]
+struct ReporterState {
+    counts: [AtomicU64; 6],
+    dropped: AtomicU64,
+    last: Mutex<Option<(SystemTime, String)>>,
+    handler: RwLock<Option<ErrorHandler>>,
+}
+
+impl Reporter {
+    pub fn report(&self, kind: FAILURE, file: impl AsRef<Path>, error: io::Error) {
+        let e = LogError { kind, file: file.as_ref().display().to_string(), error };
+        self.0.counts[kind as usize].fetch_add(1, Ordering::Relaxed);
+        *self.0.last.lock().unwrap_or_else(|e| e.into_inner()) = Some((SystemTime::now(), e.to_string()));
+        let handler = self.0.handler.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(handler) = handler {
+            handler(&e);
+        }
+    }
+
+    /// Counts a record a `DiskGuard` dropped.
+    pub fn dropped(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_handler(&self, handler: ErrorHandler) {
+        *self.0.handler.write().unwrap_or_else(|e| e.into_inner()) = Some(handler);
+    }
+
+    pub fn stats(&self) -> ErrorStats {
+        let count = |kind: FAILURE| self.0.counts[kind as usize].load(Ordering::Relaxed);
+        ErrorStats {
+            open: count(FAILURE::OPEN),
+            write: count(FAILURE::WRITE),
+            rotate: count(FAILURE::ROTATE),
+            compress: count(FAILURE::COMPRESS),
+            retention: count(FAILURE::RETENTION),
+            space: count(FAILURE::SPACE),
+            dropped: self.0.dropped.load(Ordering::Relaxed),
+            last: self.0.last.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
+    }
+}
+
+/// The timezone of log timestamps, rotation boundaries and the dates in backup names.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TIMEZONE {
+    LOCAL,
+    UTC,
+    /// A fixed offset in seconds east of UTC, e.g. `TIMEZONE::OFFSET(8 * 3600)`.
+    OFFSET(i32),
+}
+
+impl FromStr for TIMEZONE {
+    type Err = String;
+
+    /// Parses `local`, `utc` or an offset such as `+08:00`, `-0530` or `+8`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let err = || format!("invalid timezone \"{}\", expected local, utc or an offset such as +08:00", s);
+        match s.trim().to_lowercase().as_str() {
+            "local" => Ok(TIMEZONE::LOCAL),
+            "utc" | "z" => Ok(TIMEZONE::UTC),
+            t => {
+                let (sign, rest) = match t.split_at_checked(1) {
+                    Some(("+", rest)) => (1, rest),
+                    Some(("-", rest)) => (-1, rest),
+                    _ => return Err(err()),
+                };
+                let (h, m) = match rest.split_once(':') {
+                    Some(hm) => hm,
+                    None if rest.len() == 4 => rest.split_at(2),
+                    None => (rest, "0"),
+                };
+                match (h.parse::<i32>(), m.parse::<i32>()) {
+                    (Ok(h), Ok(m)) if h <= 23 && m <= 59 && !h.is_negative() && !m.is_negative() => Ok(TIMEZONE::OFFSET(sign * (h * 3600 + m * 60))),
+                    _ => Err(err()),
+                }
+            }
+        }
+    }
+}
+
+static TIMEZONE_SETTING: RwLock<TIMEZONE> = RwLock::new(TIMEZONE::LOCAL);
+
+/// Sets the timezone of every logger in the process; `TIMEZONE::LOCAL` by default.
+/// File handlers created before the change keep their next rotation time until they rotate.
+pub fn set_timezone(timezone: TIMEZONE) {
+    *TIMEZONE_SETTING.write().unwrap_or_else(|e| e.into_inner()) = timezone;
+}
+
+pub fn timezone() -> TIMEZONE {
+    *TIMEZONE_SETTING.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The codec used to compress rotated backups. Codecs other than gzip need the cargo feature of the same name.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CODEC {
+    GZIP,
+    #[cfg(feature = "zstd")]
+    ZSTD,
+    #[cfg(feature = "xz")]
+    XZ,
+    #[cfg(feature = "bzip2")]
+    BZIP2,
+    #[cfg(feature = "lz4")]
+    LZ4,
+}
+
+impl CODEC {
+    /// The extension appended to a compressed backup, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CODEC::GZIP => "gz",
+            #[cfg(feature = "zstd")]
+            CODEC::ZSTD => "zst",
+            #[cfg(feature = "xz")]
+            CODEC::XZ => "xz",
+            #[cfg(feature = "bzip2")]
+            CODEC::BZIP2 => "bz2",
+            #[cfg(feature = "lz4")]
+            CODEC::LZ4 => "lz4",
+        }
+    }
+}
+
+/// Extensions of compressed backups, matched whichever codecs are enabled so older backups still count.
+const COMPRESSED_EXTENSIONS: [&str; 5] = ["gz", "zst", "xz", "bz2", "lz4"];
+
+fn compressed_exists(path: &std::path::Path) -> bool {
+    COMPRESSED_EXTENSIONS.iter().any(|e| std::path::PathBuf::from(format!("{}.{}", path.display(), e)).exists())
+}
+
+/// Whether `open`, the metadata of an open log file, still describes the file at its path.
+/// Only Unix can tell files apart by device and inode; elsewhere an open file cannot be moved away.
+#[cfg(unix)]
+fn same_file(open: &fs::Metadata, at_path: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    open.dev() == at_path.dev() && open.ino() == at_path.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &fs::Metadata, _: &fs::Metadata) -> bool {
+    true
+}
+
+/// The limits on the backups of a log file, enforced after each rotation; a limit of 0 is disabled.
+#[derive(Clone, Copy)]
+pub(crate) struct Retention {
+    max_backups: u32,
+    max_total_size: u64,
+    max_age: Duration,
+}
+
+impl Retention {
+    fn new(fo: &dyn handle::FileOption) -> Self {
+        Retention { max_backups: fo.maxbackups(), max_total_size: fo.max_total_size(), max_age: fo.max_age() }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.max_backups > 0 || self.max_total_size > 0 || !self.max_age.is_zero()
+    }
+
+    /// Picks the backups to delete from `(modified seconds, size, path)`: those beyond the newest `max_backups`,
+    /// those that push the running total of newer backups past `max_total_size` and those older than `max_age`.
+    fn expired(&self, mut backups: Vec<(u64, u64, PathBuf)>) -> Vec<PathBuf> {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        backups.sort_by_key(|b| b.0);
+        let mut total = 0u64;
+        let mut expired = Vec::new();
+        for (i, (modified, size, path)) in backups.into_iter().rev().enumerate() {
+            total = total.saturating_add(size);
+            if (self.max_backups > 0 && i >= self.max_backups as usize) || (self.max_total_size > 0 && total > self.max_total_size) || (!self.max_age.is_zero() && now.saturating_sub(modified) > self.max_age.as_secs()) {
+                expired.push(path);
+            }
+        }
+        expired
+    }
+}
+
 // fn timenow() -> (String, String, String) {
 //     let now: DateTime<Local> = Local::now();
 //     (now.format("%Y-%m-%d").to_string(), now.format("%H:%M:%S").to_string(), now.format("%.6f").to_string())
@@ -291,67 +1063,186 @@ pub enum CUTMODE {
 //     full_format.split('|').map(|s| s.to_string()).collect()
 // }
 
-fn now() -> DateTime<Local> {
-    Local::now()
+fn now() -> DateTime<FixedOffset> {
+    match timezone() {
+        TIMEZONE::LOCAL => Local::now().fixed_offset(),
+        TIMEZONE::UTC => Utc::now().fixed_offset(),
+        TIMEZONE::OFFSET(secs) => Utc::now().with_timezone(&FixedOffset::east_opt(secs).unwrap_or(FixedOffset::east_opt(0).unwrap())),
+    }
 }
 
-fn datefmt(now: DateTime<Local>) -> String {
+fn datefmt(now: DateTime<FixedOffset>) -> String {
     format!("{:04}-{:02}-{:02}", now.year(), now.month(), now.day())
 }
 
-fn datetimefmt(now: DateTime<Local>) -> String {
+fn datetimefmt(now: DateTime<FixedOffset>) -> String {
     format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second())
 }
 
-fn microsecondfmt(now: DateTime<Local>) -> String {
+fn microsecondfmt(now: DateTime<FixedOffset>) -> String {
     format!("{:06}", now.nanosecond() / 1_000)
 }
 
-#[allow(dead_code)]
-fn zlib(filename: &str) -> io::Result<()> {
-    let input_file = File::open(filename)?;
-    let mut reader = BufReader::new(input_file);
-    let mut input_data = Vec::new();
-    reader.read_to_end(&mut input_data)?;
-    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-    e.write_all(&input_data)?;
-    let compressed_data = e.finish()?;
-    let output_filename = format!("{}.zlib", filename);
-    let output_file = File::create(&output_filename)?;
-    let mut writer = BufWriter::new(output_file);
-    let ack = writer.write_all(&compressed_data);
-    if ack.is_ok() {
-        let _ = fs::remove_file(filename);
+// Size of the chunks read from a rotated file while it is compressed.
+const COMPRESS_BUFFER: usize = 64 * 1024;
+
+// Streams `input` through the codec into `output` and returns `output` once the codec has finished.
+// A level of 0 selects the codec's default level; larger levels are capped at the codec's maximum.
+fn encode<W: Write>(codec: CODEC, level: u32, input: &mut impl Read, output: W) -> io::Result<W> {
+    match codec {
+        CODEC::GZIP => {
+            let mut encoder = GzEncoder::new(output, if level == 0 { Compression::default() } else { Compression::new(level.min(9)) });
+            copy_chunks(input, &mut encoder)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        CODEC::ZSTD => {
+            let mut encoder = zstd::stream::write::Encoder::new(output, level.min(22) as i32)?;
+            copy_chunks(input, &mut encoder)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "xz")]
+        CODEC::XZ => {
+            let mut encoder = xz2::write::XzEncoder::new(output, if level == 0 { 6 } else { level.min(9) });
+            copy_chunks(input, &mut encoder)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "bzip2")]
+        CODEC::BZIP2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(output, if level == 0 { bzip2::Compression::default() } else { bzip2::Compression::new(level.min(9)) });
+            copy_chunks(input, &mut encoder)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "lz4")]
+        CODEC::LZ4 => {
+            let mut encoder = lz4::EncoderBuilder::new().level(level.min(16)).build(output)?;
+            copy_chunks(input, &mut encoder)?;
+            let (output, r) = encoder.finish();
+            r.map(|_| output)
+        }
+    }
+}
+
+fn copy_chunks(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {
+    let mut buf = vec![0u8; COMPRESS_BUFFER];
+    loop {
+        match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => output.write_all(&buf[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
     }
-    Ok(())
 }
 
-fn gzip(filename: &str) -> io::Result<()> {
+// The archive is written to a temporary file that is fsynced and renamed into place before the log file is removed,
+// so a crash leaves either the log file or a complete archive. The backup keeps the modification time of the log file,
+// which orders backups for max_backups.
+fn compress_file(filename: &str, codec: CODEC, level: u32) -> io::Result<()> {
     let mut input_file = File::open(filename)?;
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    io::copy(&mut input_file, &mut encoder)?;
-    let compressed_data = encoder.finish()?;
-    let output_filename = format!("{}.gz", filename);
-    let mut output_file = File::create(&output_filename)?;
-    let ack = output_file.write_all(&compressed_data);
-    if ack.is_ok() {
-        let _ = fs::remove_file(filename);
-    }
-    Ok(())
-}
-
-async fn async_gzip(filename: &str) -> io::Result<()> {
-    let mut input_file = tokio::fs::File::open(filename).await?;
-    let mut file_content = Vec::new();
-    input_file.read_to_end(&mut file_content).await?;
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    let _ = encoder.write_all(&file_content);
-    let compressed_data = encoder.finish()?;
-    let output_filename = format!("{}.gz", filename);
-    let mut output_file = tokio::fs::File::create(output_filename).await?;
-    tokio::io::AsyncWriteExt::write_all(&mut output_file, &compressed_data).await?;
-    let _ = tokio::fs::remove_file(filename).await?;
-    Ok(())
+    let modified = input_file.metadata()?.modified()?;
+    let output_filename = format!("{}.{}", filename, codec.extension());
+    let tmp_filename = format!("{}.tmp", output_filename);
+    let r = File::create(&tmp_filename).and_then(|f| {
+        let output_file = encode(codec, level, &mut input_file, io::BufWriter::with_capacity(COMPRESS_BUFFER, f))?.into_inner().map_err(|e| e.into_error())?;
+        output_file.sync_all()?;
+        let _ = output_file.set_modified(modified);
+        fs::rename(&tmp_filename, &output_filename)
+    });
+    if r.is_err() {
+        let _ = fs::remove_file(&tmp_filename);
+        return r;
+    }
+    sync_parent(Path::new(&output_filename));
+    fs::remove_file(filename)
+}
+
+// Makes a rename durable; not every platform can open a directory, so failures are ignored.
+fn sync_parent(path: &Path) {
+    if let Some(parent) = path.parent() {
+        let dir = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+}
+
+/// The period a backup is named after; size rotation has none.
+fn period(cutmode: CUTMODE, startsec: u64, timemode: MODE) -> Option<(u64, MODE)> {
+    match cutmode {
+        CUTMODE::SIZE => None,
+        CUTMODE::TIME | CUTMODE::MIXED => Some((startsec, timemode)),
+    }
+}
+
+/// The directory of a log file, the current directory for a bare file name.
+fn log_dir(path: &Path) -> io::Result<PathBuf> {
+    let parent = path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::Other, ErrCode::NotFound.to_string()))?;
+    if parent.as_os_str().is_empty() {
+        return env::current_dir();
+    }
+    Ok(parent.to_path_buf())
+}
+
+// The log paths this process has opened, with the file the first handler of each chose.
+static STARTED: Lazy<Mutex<HashMap<PathBuf, PathBuf>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The file the first handler of `log_path` in this process opened, if there was one. Only that handler
+/// applies the startup policy, so rebuilding the handlers on a config reload neither rotates the live
+/// file nor starts another per-run file.
+fn started_file(log_path: &Path) -> Option<PathBuf> {
+    let key = std::path::absolute(log_path).unwrap_or_else(|_| log_path.to_path_buf());
+    STARTED.lock().unwrap_or_else(|e| e.into_inner()).get(&key).cloned()
+}
+
+fn set_started_file(log_path: &Path, file: &Path) {
+    let key = std::path::absolute(log_path).unwrap_or_else(|_| log_path.to_path_buf());
+    STARTED.lock().unwrap_or_else(|e| e.into_inner()).entry(key).or_insert_with(|| file.to_path_buf());
+}
+
+/// Opens the lock file that processes sharing `log_path` take turns on, `.{name}.lock` next to it.
+fn lock_file(log_path: &Path) -> io::Result<File> {
+    let name = log_path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no file name in {}", log_path.display())))?;
+    fs::OpenOptions::new().create(true).truncate(false).write(true).open(log_path.with_file_name(format!(".{}.lock", name.to_string_lossy())))
+}
+
+/// Points the symlink `link` at `target`, a file in the same directory, replacing any previous link
+/// in one rename so readers never find the path missing.
+fn update_link(link: &Path, target: &Path) -> io::Result<()> {
+    let (Some(name), Some(link_name)) = (target.file_name(), link.file_name()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("cannot link {} to {}", link.display(), target.display())));
+    };
+    let tmp = link.with_file_name(format!(".{}.tmp", link_name.to_string_lossy()));
+    let _ = fs::remove_file(&tmp);
+    symlink(Path::new(name), &tmp)?;
+    fs::rename(&tmp, link).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links are not supported on this platform"))
+}
+
+async fn async_update_link(link: &Path, target: &Path) -> io::Result<()> {
+    let (link, target) = (link.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || update_link(&link, &target)).await.map_err(io::Error::other)?
+}
+
+async fn async_compress(filename: &str, codec: CODEC, level: u32) -> io::Result<()> {
+    let filename = filename.to_string();
+    tokio::task::spawn_blocking(move || compress_file(&filename, codec, level)).await.map_err(io::Error::other)?
 }
 
 fn parse_and_format_log(format_str: &str, level: &str, time: &str, file: &str, message: &str) -> String {
@@ -384,41 +1275,199 @@ fn parse_and_format_log(format_str: &str, level: &str, time: &str, file: &str, m
 }
 
 fn getbackup_with_time(startsec: u64, timemode: MODE) -> String {
-    let start_time = DateTime::from_timestamp(startsec as i64, 0).expect("");
+    let start_time = wall_time(startsec);
     match timemode {
-        MODE::HOUR => {
-            let formatted_time = start_time.format("%Y%m%d%H");
-            formatted_time.to_string()
+        MODE::MINUTE | MODE::CRON(_) => start_time.format("%Y%m%d%H%M").to_string(),
+        MODE::HOUR => start_time.format("%Y%m%d%H").to_string(),
+        MODE::DAY => start_time.format("%Y%m%d").to_string(),
+        MODE::WEEK(_) => week_start(start_time.date(), timemode).format("%Y%m%d").to_string(),
+        MODE::MONTH => start_time.format("%Y%m").to_string(),
+    }
+}
+
+fn week_start(date: NaiveDate, timemode: MODE) -> NaiveDate {
+    let first = if let MODE::WEEK(first) = timemode { first } else { Weekday::Mon };
+    date - chrono::Duration::days(((7 + date.weekday().num_days_from_monday() - first.num_days_from_monday()) % 7) as i64)
+}
+
+/// When a file started at `startsec` is due for rotation, as Unix seconds like `timesec`.
+/// Boundaries fall on the wall clock of the configured timezone; `u64::MAX` when a schedule never matches.
+fn next_rotation(startsec: u64, timemode: MODE) -> u64 {
+    let start = wall_time(startsec);
+    let date = start.date();
+    let next = match timemode {
+        MODE::MINUTE => start.with_second(0).map(|t| t + chrono::Duration::minutes(1)),
+        MODE::HOUR => date.and_hms_opt(start.hour(), 0, 0).map(|t| t + chrono::Duration::hours(1)),
+        MODE::DAY => date.succ_opt().and_then(|d| d.and_hms_opt(0, 0, 0)),
+        MODE::WEEK(_) => (week_start(date, timemode) + chrono::Duration::days(7)).and_hms_opt(0, 0, 0),
+        MODE::MONTH => {
+            let (y, m) = if date.month() == 12 { (date.year() + 1, 1) } else { (date.year(), date.month() + 1) };
+            NaiveDate::from_ymd_opt(y, m, 1).and_then(|d| d.and_hms_opt(0, 0, 0))
         }
-        MODE::DAY => {
-            let formatted_time = start_time.format("%Y%m%d");
-            formatted_time.to_string()
+        MODE::CRON(schedule) => schedule.next_after(start),
+    };
+    next.map_or(u64::MAX, unix_time)
+}
+
+/// The wall clock time in the configured timezone of `secs` Unix seconds.
+fn wall_time(secs: u64) -> NaiveDateTime {
+    let utc = DateTime::from_timestamp(secs as i64, 0).unwrap_or_default();
+    match timezone() {
+        TIMEZONE::LOCAL => utc.with_timezone(&Local).naive_local(),
+        TIMEZONE::UTC => utc.naive_utc(),
+        TIMEZONE::OFFSET(offset) => utc.naive_utc() + chrono::Duration::seconds(offset as i64),
+    }
+}
+
+/// The Unix seconds of a wall clock time in the configured timezone. A local time repeated when
+/// daylight saving time ends resolves to its first occurrence, and one skipped when it starts to the end of the gap.
+fn unix_time(t: NaiveDateTime) -> u64 {
+    let secs = match timezone() {
+        TIMEZONE::LOCAL => {
+            let mut t = t;
+            // a skipped local time moves forward until it exists; gaps are at most a few hours
+            loop {
+                if let Some(local) = Local.from_local_datetime(&t).earliest() {
+                    break local.timestamp();
+                }
+                t += chrono::Duration::minutes(15);
+            }
         }
-        MODE::MONTH => {
-            let formatted_date = start_time.format("%Y%m");
-            formatted_date.to_string()
+        TIMEZONE::UTC => t.and_utc().timestamp(),
+        TIMEZONE::OFFSET(offset) => t.and_utc().timestamp() - offset as i64,
+    };
+    secs.max(0) as u64
+}
+
+fn level_name(level: LEVEL) -> &'static str {
+    match level {
+        LEVEL::Trace => "TRACE",
+        LEVEL::Debug => "DEBUG",
+        LEVEL::Info => "INFO",
+        LEVEL::Warn => "WARN",
+        LEVEL::Error => "ERROR",
+        LEVEL::Fatal => "FATAL",
+        LEVEL::Off => "",
+    }
+}
+
+fn json_escape(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn text_fields<'a>(msg: &'a str, fields: &[(String, FieldValue)]) -> std::borrow::Cow<'a, str> {
+    if fields.is_empty() {
+        return std::borrow::Cow::Borrowed(msg);
+    }
+    let mut r = String::from(msg);
+    for (k, v) in fields {
+        if !r.is_empty() {
+            r.push(' ');
+        }
+        r.push_str(k);
+        r.push('=');
+        match v {
+            FieldValue::Str(s) if s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') => r.push_str(format!("{:?}", s).as_str()),
+            _ => r.push_str(v.to_string().as_str()),
+        }
+    }
+    std::borrow::Cow::Owned(r)
+}
+
+fn json_value(out: &mut String, v: &FieldValue) {
+    match v {
+        FieldValue::Str(s) => json_escape(out, s),
+        FieldValue::F64(f) if !f.is_finite() => json_escape(out, f.to_string().as_str()),
+        _ => out.push_str(v.to_string().as_str()),
+    }
+}
+
+// The parts of a record that `log_fmt` lays out, as given to the logger.
+struct FmtRecord<'a> {
+    level: LEVEL,
+    module: &'a str,
+    filename: &'a str,
+    line: u32,
+    msg: &'a str,
+    fields: &'a [(String, FieldValue)],
+}
+
+// Keys `json_fmt` writes itself; fields with these names are written as `fields.<name>` instead.
+const JSON_KEYS: [&str; 6] = ["level", "time", "module", "file", "line", "message"];
+
+// `filename` is the file name to print, empty when the format leaves it out.
+fn json_fmt(levelflag: &str, time: &str, filename: &str, rec: &FmtRecord) -> String {
+    let mut r = String::with_capacity(levelflag.len() + time.len() + rec.module.len() + filename.len() + rec.msg.len() + 64);
+    r.push('{');
+    if !levelflag.is_empty() {
+        r.push_str("\"level\":");
+        json_escape(&mut r, levelflag);
+        r.push(',');
+    }
+    if !time.is_empty() {
+        r.push_str("\"time\":");
+        json_escape(&mut r, time);
+        r.push(',');
+    }
+    r.push_str("\"module\":");
+    json_escape(&mut r, rec.module);
+    r.push(',');
+    if !filename.is_empty() {
+        r.push_str("\"file\":");
+        json_escape(&mut r, filename);
+        r.push_str(",\"line\":");
+        r.push_str(rec.line.to_string().as_str());
+        r.push(',');
+    }
+    r.push_str("\"message\":");
+    json_escape(&mut r, rec.msg);
+    for (k, v) in rec.fields {
+        r.push(',');
+        if JSON_KEYS.contains(&k.as_str()) {
+            json_escape(&mut r, format!("fields.{}", k).as_str());
+        } else {
+            json_escape(&mut r, k);
         }
+        r.push(':');
+        json_value(&mut r, v);
     }
+    r.push_str("}\n");
+    r
 }
 
-fn log_fmt<LF, TF>(levelfmt: Option<LF>, timefmt: Option<TF>, fmat: u8, formatter: Option<&String>, level: LEVEL, filename: &str, line: u32, msg: &str) -> String
+fn log_fmt<LF, TF>(levelfmt: Option<LF>, timefmt: Option<TF>, fmat: u8, formatter: Option<&String>, rec: &FmtRecord) -> String
 where
     LF: Fn(LEVEL) -> String,
     TF: Fn() -> (String, String, String),
 {
     if fmat == Format::Nano {
-        return msg.to_string();
+        return text_fields(rec.msg, rec.fields).into_owned();
     }
 
+    let is_json = fmat & Format::Json != 0;
     let mut levelflag = String::new();
     let mut time = String::new();
     let mut file = String::new();
 
     if fmat & Format::LevelFlag != 0 {
         if let Some(f) = levelfmt {
-            levelflag = f(level);
+            levelflag = f(rec.level);
+        } else if is_json {
+            levelflag = level_name(rec.level).to_string();
         } else {
-            levelflag = match level {
+            levelflag = match rec.level {
                 LEVEL::Trace => "[TRACE]",
                 LEVEL::Debug => "[DEBUG]",
                 LEVEL::Info => "[INFO]",
@@ -476,15 +1525,24 @@ where
         }
     }
     if fmat & (Format::LongFileName | Format::ShortFileName) != 0 {
-        let mut f = filename;
+        let mut f = rec.filename;
         if fmat & Format::ShortFileName != 0 {
             f = get_short_file_path(f)
         }
+        if is_json {
+            return json_fmt(levelflag.as_str(), time.as_str(), f, rec);
+        }
         file.push_str(f);
         file.push(' ');
-        file.push_str(line.to_string().as_str());
+        file.push_str(rec.line.to_string().as_str());
     }
 
+    if is_json {
+        return json_fmt(levelflag.as_str(), time.as_str(), "", rec);
+    }
+
+    let msg = text_fields(rec.msg, rec.fields);
+
     if formatter.is_none() {
         let mut r = String::with_capacity(levelflag.len() + time.len() + file.len() + msg.len() + 4);
         if !levelflag.is_empty() {
@@ -505,7 +1563,7 @@ where
         return r;
     } else {
         let fmts = formatter.unwrap();
-        return parse_and_format_log(fmts.as_str(), levelflag.as_str(), time.as_str(), file.as_str(), msg);
+        return parse_and_format_log(fmts.as_str(), levelflag.as_str(), time.as_str(), file.as_str(), &msg);
     }
 }
 
@@ -524,23 +1582,7 @@ fn get_short_file_path(filename: &str) -> &str {
 }
 
 fn timesec() -> u64 {
-    let now: NaiveDateTime = Local::now().naive_local();
-    return now.and_utc().timestamp() as u64;
-}
-
-fn passtimemode(startsec: u64, timemode: MODE) -> bool {
-    let start_time = DateTime::from_timestamp(startsec as i64, 0).expect("");
-    let now: NaiveDateTime = Local::now().naive_local();
-
-    let (now_year, now_month, now_day, now_hour) = (now.year(), now.month(), now.day(), now.hour());
-
-    let (start_year, start_month, start_day, start_hour) = (start_time.year(), start_time.month(), start_time.day(), start_time.hour());
-
-    match timemode {
-        MODE::HOUR => now_year > start_year || (now_year == start_year && now_month > start_month) || (now_year == start_year && now_month == start_month && now_day > start_day) || (now_year == start_year && now_month == start_month && now_day == start_day && now_hour > start_hour),
-        MODE::DAY => now_year > start_year || (now_year == start_year && now_month > start_month) || (now_year == start_year && now_month == start_month && now_day > start_day),
-        MODE::MONTH => now_year > start_year || (now_year == start_year && now_month > start_month),
-    }
+    Utc::now().timestamp().max(0) as u64
 }
 
 fn l2tk(level: log::Level) -> LEVEL {
@@ -553,6 +1595,36 @@ fn l2tk(level: log::Level) -> LEVEL {
     }
 }
 
+struct FieldVisitor<'a>(&'a mut Vec<(String, FieldValue)>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        let v = if let Some(b) = value.to_bool() {
+            FieldValue::Bool(b)
+        } else if let Some(i) = value.to_i64() {
+            FieldValue::I64(i)
+        } else if let Some(u) = value.to_u64() {
+            FieldValue::U64(u)
+        } else if let Some(f) = value.to_f64() {
+            FieldValue::F64(f)
+        } else if let Some(s) = value.to_borrowed_str() {
+            FieldValue::Str(s.to_string())
+        } else {
+            FieldValue::Str(value.to_string())
+        };
+        self.0.push((key.as_str().to_string(), v));
+        Ok(())
+    }
+}
+
+/// Collects the key-value pairs of a `log` record as tklog fields.
+fn record_fields(record: &log::Record) -> Vec<(String, FieldValue)> {
+    let kvs = record.key_values();
+    let mut fields = Vec::with_capacity(kvs.count());
+    let _ = kvs.visit(&mut FieldVisitor(&mut fields));
+    fields
+}
+
 fn arguments_to_string(args: &std::fmt::Arguments) -> String {
     fmt::format(*args)
 }
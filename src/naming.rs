@@ -0,0 +1,204 @@
+// Copyright (c) 2024, donnie4w <donnie4w@gmail.com>
+// All rights reserved.
+// https://github.com/donnie4w/tklog
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{compressed_exists, getbackup_with_time, timesec, wall_time, COMPRESSED_EXTENSIONS, MODE};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Stem,
+    Ext,
+    Counter,
+    Date,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+}
+
+/// Names the backups of one log file and recognizes them again for retention.
+/// Without a template backups are named `{stem}_{date}_{counter}.{ext}`, or `{stem}_{counter}.{ext}` when rotating by size.
+#[derive(Clone)]
+pub(crate) struct BackupName {
+    filename: String,
+    stem: String,
+    extension: String,
+    template: Option<Vec<Token>>,
+    pattern: Regex,
+}
+
+impl BackupName {
+    pub fn new(log_path: &Path, template: Option<String>) -> Self {
+        let stem = log_path.file_stem().unwrap_or_else(|| OsStr::new("tklog")).to_string_lossy().to_string();
+        let extension = log_path.extension().map_or(String::new(), |e| e.to_string_lossy().to_string());
+        let filename = log_path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+        let template = template.map(|t| {
+            let mut tokens = parse(&t).unwrap_or_else(|_| vec![Token::Text(t)]);
+            // "{stem}.{ext}" must not leave a trailing dot behind for a file without extension
+            if extension.is_empty() {
+                for i in 1..tokens.len() {
+                    if tokens[i] == Token::Ext {
+                        if let Token::Text(s) = &mut tokens[i - 1] {
+                            if s.ends_with('.') {
+                                s.pop();
+                            }
+                        }
+                    }
+                }
+            }
+            tokens
+        });
+        let pattern = pattern(&stem, &extension, template.as_deref());
+        BackupName { filename, stem, extension, template, pattern }
+    }
+
+    /// The `counter`th candidate name, from 1, for a backup of the period starting at `time`.
+    /// Size rotation passes no period; its date placeholders use the current day.
+    pub fn name(&self, time: Option<(u64, MODE)>, counter: u32) -> String {
+        let suffix = if self.extension.is_empty() { String::new() } else { format!(".{}", self.extension) };
+        let Some(tokens) = &self.template else {
+            return match time {
+                Some((startsec, timemode)) => format!("{}_{}_{}{}", self.stem, getbackup_with_time(startsec, timemode), counter, suffix),
+                None => format!("{}_{}{}", self.stem, counter, suffix),
+            };
+        };
+        let (startsec, timemode) = time.unwrap_or_else(|| (timesec(), MODE::DAY));
+        let start = wall_time(startsec);
+        let mut name = String::new();
+        for token in tokens {
+            match token {
+                Token::Text(s) => name.push_str(s),
+                Token::Stem => name.push_str(&self.stem),
+                Token::Ext => name.push_str(&self.extension),
+                Token::Counter => name.push_str(&counter.to_string()),
+                Token::Date => name.push_str(&getbackup_with_time(startsec, timemode)),
+                Token::Year => name.push_str(&start.format("%Y").to_string()),
+                Token::Month => name.push_str(&start.format("%m").to_string()),
+                Token::Day => name.push_str(&start.format("%d").to_string()),
+                Token::Hour => name.push_str(&start.format("%H").to_string()),
+                Token::Minute => name.push_str(&start.format("%M").to_string()),
+            }
+        }
+        // a template without {counter} only numbers the backups of a period after the first one
+        if counter > 1 && !tokens.contains(&Token::Counter) {
+            name.push_str(&format!(".{}", counter - 1));
+        }
+        name
+    }
+
+    /// The name of the log file of a process run started at `startsec`, e.g. `app_20261018134501_4242.log`.
+    /// It has the shape of a default backup name, so retention counts the files of earlier runs.
+    pub fn run_name(&self, startsec: u64) -> String {
+        let suffix = if self.extension.is_empty() { String::new() } else { format!(".{}", self.extension) };
+        format!("{}_{}_{}{}", self.stem, wall_time(startsec).format("%Y%m%d%H%M%S"), std::process::id(), suffix)
+    }
+
+    /// The first name for the period starting at `time` that no file in `dir` has yet, compressed or not.
+    pub fn free_path(&self, dir: &Path, time: Option<(u64, MODE)>) -> PathBuf {
+        let mut counter = 1;
+        loop {
+            let path = dir.join(self.name(time, counter));
+            if !path.exists() && !compressed_exists(&path) {
+                return path;
+            }
+            counter += 1;
+        }
+    }
+
+    /// Whether `file_name` is one of the backups, compressed or not.
+    pub fn matches(&self, file_name: &str) -> bool {
+        file_name != self.filename && self.pattern.is_match(file_name)
+    }
+}
+
+// The pattern of every name `BackupName::name` can produce, with any compressed extension.
+fn pattern(stem: &str, extension: &str, template: Option<&[Token]>) -> Regex {
+    let compressed = COMPRESSED_EXTENSIONS.join("|");
+    let Some(tokens) = template else {
+        let suffix = if extension.is_empty() { String::new() } else { format!("\\.{}", regex::escape(extension)) };
+        return Regex::new(&format!("^{}(_\\d+){{0,}}_\\d+{}(\\.({}))?$", regex::escape(stem), suffix, compressed)).unwrap();
+    };
+    let mut pattern = String::from("^");
+    for token in tokens {
+        match token {
+            Token::Text(s) => pattern.push_str(&regex::escape(s)),
+            Token::Stem => pattern.push_str(&regex::escape(stem)),
+            Token::Ext => pattern.push_str(&regex::escape(extension)),
+            Token::Counter | Token::Date => pattern.push_str("\\d+"),
+            Token::Year => pattern.push_str("\\d{4}"),
+            Token::Month | Token::Day | Token::Hour | Token::Minute => pattern.push_str("\\d{2}"),
+        }
+    }
+    if !tokens.contains(&Token::Counter) {
+        pattern.push_str("(\\.\\d+)?");
+    }
+    Regex::new(&format!("{}(\\.({}))?$", pattern, compressed)).unwrap()
+}
+
+/// Checks a backup name template, which may use `{stem}`, `{ext}`, `{counter}`, `{date}`,
+/// `{YYYY}`, `{MM}`, `{DD}`, `{hh}` and `{mm}`.
+pub fn check_template(template: &str) -> Result<(), String> {
+    let tokens = parse(template)?;
+    if tokens.iter().any(|t| matches!(t, Token::Text(s) if s.contains('/') || s.contains('\\'))) {
+        return Err(format!("backup name \"{}\" must not contain a path separator", template));
+    }
+    if !tokens.iter().any(|t| !matches!(t, Token::Text(_))) {
+        return Err(format!("backup name \"{}\" has no placeholder, so every backup would get the same name", template));
+    }
+    Ok(())
+}
+
+fn parse(template: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            return Err(format!("unclosed placeholder in backup name \"{}\"", template));
+        };
+        let token = match &rest[start + 1..start + len] {
+            "stem" => Token::Stem,
+            "ext" => Token::Ext,
+            "counter" => Token::Counter,
+            "date" => Token::Date,
+            "YYYY" => Token::Year,
+            "MM" => Token::Month,
+            "DD" => Token::Day,
+            "hh" => Token::Hour,
+            "mm" => Token::Minute,
+            other => return Err(format!("unknown placeholder {{{}}} in backup name \"{}\", expected one of {{stem}}, {{ext}}, {{counter}}, {{date}}, {{YYYY}}, {{MM}}, {{DD}}, {{hh}}, {{mm}}", other, template)),
+        };
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(token);
+        rest = &rest[start + len + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
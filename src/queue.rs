@@ -0,0 +1,185 @@
+// Copyright (c) 2024, donnie4w <donnie4w@gmail.com>
+// All rights reserved.
+// https://github.com/donnie4w/tklog
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, SendTimeoutError, Sender, TryRecvError, TrySendError};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+use crate::{LEVEL, OVERFLOW};
+
+pub(crate) type Ack = Box<dyn FnOnce() + Send + 'static>;
+
+pub(crate) enum Message {
+    Log(LEVEL, String, String),
+    Flush(Ack),
+    Shutdown(Ack),
+}
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+// The writer looks for due file buffers at least this often, so a file configured after it started is not missed for long.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+const SEND_RETRY: Duration = Duration::from_millis(100);
+
+/// The channel between a logger and its `PRINTMODE::DELAY` writer.
+/// Clones share the channel, so a record can be queued after the logger lock is released.
+/// Only the writer receives from it, so the writer stops once the logger and its clones are gone.
+#[derive(Clone)]
+pub(crate) struct Queue {
+    sender: Sender<Message>,
+    // Takes the oldest record out for `OVERFLOW::DropOldest`; None under the other policies.
+    evict: Option<Receiver<Message>>,
+    overflow: OVERFLOW,
+    dropped: Arc<AtomicU64>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Queue {
+    /// Creates a queue and spawns `writer` on a new thread to receive from it; see [`run`].
+    /// A capacity of 0 creates an unbounded queue.
+    pub fn spawn<F>(capacity: usize, overflow: OVERFLOW, writer: F) -> Self
+    where
+        F: FnOnce(Receiver<Message>, Arc<AtomicU64>, Arc<AtomicBool>) + Send + 'static,
+    {
+        let (sender, receiver) = if capacity == 0 { unbounded() } else { bounded(capacity) };
+        let evict = if overflow == OVERFLOW::DropOldest { Some(receiver.clone()) } else { None };
+        let queue = Queue { sender, evict, overflow, dropped: Arc::new(AtomicU64::new(0)), closed: Arc::new(AtomicBool::new(false)) };
+        let (dropped, closed) = (queue.dropped.clone(), queue.closed.clone());
+        std::thread::spawn(move || writer(receiver, dropped, closed));
+        queue
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the writer has stopped; records pushed afterwards are discarded.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn push(&self, level: LEVEL, module: String, message: String) {
+        if self.is_closed() {
+            return;
+        }
+        let mut msg = Message::Log(level, module, message);
+        loop {
+            match self.sender.try_send(msg) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => return,
+                Err(TrySendError::Full(m)) => msg = m,
+            }
+            match self.overflow {
+                OVERFLOW::Block => {
+                    self.send(msg);
+                    return;
+                }
+                OVERFLOW::DropBelow(l) if level >= l => {
+                    self.send(msg);
+                    return;
+                }
+                OVERFLOW::DropNewest | OVERFLOW::DropBelow(_) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                OVERFLOW::DropOldest => match self.evict.as_ref().map_or(Err(TryRecvError::Empty), |r| r.try_recv()) {
+                    Ok(Message::Log(..)) => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // flush and shutdown markers are never dropped, only moved behind newer records
+                    Ok(m) => {
+                        self.send(m);
+                    }
+                    Err(_) => {}
+                },
+            }
+        }
+    }
+
+    /// Sends a flush or shutdown marker, waiting for room regardless of the overflow policy.
+    pub fn control(&self, msg: Message) -> bool {
+        !self.is_closed() && self.send(msg)
+    }
+
+    // Waits for room, giving up once the writer has stopped.
+    fn send(&self, mut msg: Message) -> bool {
+        loop {
+            match self.sender.send_timeout(msg, SEND_RETRY) {
+                Ok(()) => return true,
+                Err(SendTimeoutError::Timeout(m)) => {
+                    if self.is_closed() {
+                        return false;
+                    }
+                    msg = m;
+                }
+                Err(SendTimeoutError::Disconnected(_)) => return false,
+            }
+        }
+    }
+}
+
+/// The writer loop: prints queued records until a shutdown marker arrives.
+/// Newly dropped records are reported every `REPORT_INTERVAL` and before each flush is acknowledged.
+/// `tick` writes the file buffers that are due and returns when to call it again, at most `TICK_INTERVAL` later.
+pub(crate) fn run(
+    receiver: Receiver<Message>,
+    dropped: Arc<AtomicU64>,
+    closed: Arc<AtomicBool>,
+    mut print: impl FnMut(LEVEL, &str, &str),
+    mut report: impl FnMut(u64),
+    mut tick: impl FnMut() -> Option<Duration>,
+) {
+    let mut reported = 0;
+    let mut last = Instant::now();
+    let mut tick_at = Instant::now() + TICK_INTERVAL;
+    let mut report_dropped = |reported: &mut u64| {
+        let n = dropped.load(Ordering::Relaxed);
+        if n > *reported {
+            report(n - *reported);
+            *reported = n;
+        }
+    };
+    loop {
+        match receiver.recv_timeout(tick_at.saturating_duration_since(Instant::now())) {
+            Ok(Message::Log(level, module, msg)) => print(level, module.as_str(), msg.as_str()),
+            Ok(Message::Flush(ack)) => {
+                report_dropped(&mut reported);
+                ack();
+            }
+            Ok(Message::Shutdown(ack)) => {
+                report_dropped(&mut reported);
+                closed.store(true, Ordering::Relaxed);
+                ack();
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if last.elapsed() >= REPORT_INTERVAL {
+            report_dropped(&mut reported);
+            last = Instant::now();
+        }
+        if Instant::now() >= tick_at {
+            tick_at = Instant::now() + tick().map_or(TICK_INTERVAL, |wait| wait.min(TICK_INTERVAL));
+        }
+    }
+}
+
+pub(crate) fn dropped_message(n: u64) -> String {
+    format!("tklog dropped {} log records: queue full", n)
+}
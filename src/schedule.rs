@@ -0,0 +1,157 @@
+// Copyright (c) 2024, donnie4w <donnie4w@gmail.com>
+// All rights reserved.
+// https://github.com/donnie4w/tklog
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cron-like rotation schedules for `MODE::CRON`.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+// Searching further than this finds no match, e.g. for February 30.
+const SEARCH_LIMIT: u32 = 100_000;
+
+/// A cron-like schedule of five fields: minute, hour, day of month, month and day of week.
+///
+/// Each field is `*`, a number, a range `a-b` or a comma-separated list of them, optionally stepped
+/// with `/n` (`*/15`, `8-18/2`). Days of week run from 0 (Sunday) to 7 (Sunday again). As in cron, a
+/// day matches either day field when both are restricted. `@hourly`, `@daily`, `@weekly`, `@monthly`
+/// and `@yearly` are accepted as shorthands.
+///
+/// ```
+/// use chrono::NaiveDate;
+///
+/// let every_six_hours: tklog::schedule::Schedule = "0 */6 * * *".parse().unwrap();
+/// let t = NaiveDate::from_ymd_opt(2024, 5, 21).unwrap().and_hms_opt(13, 30, 0).unwrap();
+/// assert_eq!(every_six_hours.next_after(t), NaiveDate::from_ymd_opt(2024, 5, 21).unwrap().and_hms_opt(18, 0, 0));
+/// // rotate the log file every six hours
+/// let mode = tklog::MODE::CRON(every_six_hours);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// The first minute after `after` that the schedule matches, or `None` when it never matches.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        for _ in 0..SEARCH_LIMIT {
+            if !bit(self.months as u64, t.month()) {
+                let (y, m) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = NaiveDate::from_ymd_opt(y, m, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !bit(self.hours as u64, t.hour()) {
+                t = t.date().and_hms_opt(t.hour(), 0, 0)? + Duration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = bit(self.days as u64, date.day());
+        let weekday = bit(self.weekdays as u64, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn bit(mask: u64, n: u32) -> bool {
+    mask & (1 << n) != 0
+}
+
+// Parses one field into a bit mask, returning whether it was `*`.
+fn field(s: &str, name: &str, min: u32, max: u32) -> Result<(u64, bool), String> {
+    let mut mask = 0u64;
+    for item in s.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((r, st)) => (r, st.parse::<u32>().ok().filter(|st| *st > 0).ok_or_else(|| format!("invalid step \"{}\" in {} field", st, name))?),
+            None => (item, 1),
+        };
+        let number = |n: &str| n.parse::<u32>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(|| format!("{} \"{}\" is out of range {}-{}", name, n, min, max));
+        let (from, to) = match range {
+            "*" => (min, max),
+            r => match r.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                None if step > 1 => (number(r)?, max),
+                None => (number(r)?, number(r)?),
+            },
+        };
+        if from > to {
+            return Err(format!("invalid range \"{}\" in {} field", range, name));
+        }
+        for n in (from..=to).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok((mask, s == "*"))
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let expr = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            e => e,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("invalid schedule \"{}\", expected five fields: minute hour day-of-month month day-of-week", s));
+        }
+        let (minutes, _) = field(fields[0], "minute", 0, 59)?;
+        let (hours, _) = field(fields[1], "hour", 0, 23)?;
+        let (days, any_day) = field(fields[2], "day of month", 1, 31)?;
+        let (months, _) = field(fields[3], "month", 1, 12)?;
+        let (weekdays, any_weekday) = field(fields[4], "day of week", 0, 7)?;
+        // 7 is Sunday as well
+        let weekdays = (weekdays | weekdays >> 7) as u8 & 0x7f;
+        Ok(Schedule { minutes, hours: hours as u32, days: days as u32, months: months as u16, weekdays, any_day, any_weekday })
+    }
+}
+
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |mask: u64, min: u32, max: u32| (min..=max).filter(|n| bit(mask, *n)).map(|n| n.to_string()).collect::<Vec<_>>().join(",");
+        write!(
+            f,
+            "Schedule({} {} {} {} {})",
+            list(self.minutes, 0, 59),
+            list(self.hours as u64, 0, 23),
+            if self.any_day { "*".to_string() } else { list(self.days as u64, 1, 31) },
+            list(self.months as u64, 1, 12),
+            if self.any_weekday { "*".to_string() } else { list(self.weekdays as u64, 0, 6) }
+        )
+    }
+}
@@ -16,18 +16,26 @@
 
 use crate::{
     arguments_to_string,
-    handle::{FHandler, FileOptionType, FmtHandler},
+    config::{self, Config, ConfigError, Settings},
+    env_mod_directives,
+    handle::{flush_sinks, write_sinks, FHandler, FileOption, FileOptionType, FmtHandler, SinkRef},
     l2tk, log_fmt,
+    queue::{self, Ack, Message, Queue},
+    record_fields,
     syncfile::FileHandler,
     tklog::synclog,
     trie::Trie,
-    AttrFormat, Format, LogContext, LogOption, LogOptionConst, OptionTrait, LEVEL, MODE, PRINTMODE, TKLOG2SYNCLOG,
+    AttrFormat, Directive, ErrorStats, FieldValue, FmtRecord, Format, LogContext, LogError, LogOption, LogOptionConst, OptionTrait, Reporter, SinkOption, FAILURE, LEVEL, MODE, OVERFLOW, PRINTMODE, TKLOG2SYNCLOG,
 };
-use std::thread;
 use std::{
     collections::HashMap,
-    sync::mpsc::{channel, Sender},
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc::channel, Arc, Mutex, MutexGuard},
+    time::Duration,
 };
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
 
 /// this is the tklog encapsulated Logger whose File operations
 /// are based on the standard library std::fs::File,therefore,
@@ -52,7 +60,7 @@ use std::{
 ///     .set_cutmode_by_size("tklog.log", 1<<20, 0, true);
 /// ```
 pub struct Logger {
-    sender: Sender<(LEVEL, String, String)>,
+    queue: Queue,
     fmthandle: FmtHandler,
     filehandle: (String, FHandler),
     mutex: std::sync::Mutex<u32>,
@@ -61,25 +69,18 @@ pub struct Logger {
     fmap: HashMap<String, FHandler>,
     custom_handler: Option<fn(&LogContext) -> bool>,
     separator: String,
-    levels: Option<[Option<(LogOption, String)>; 7]>,
+    levels: Option<[Option<(LogOptionConst, String)>; 7]>,
     // levelfmt: Option<Box<dyn Fn(LEVEL) -> String + Send + Sync>>,
     // timefmt: Option<Box<dyn Fn() -> (String, String, String) + Send + Sync>>,
     attrfmt: AttrFormat,
+    config_path: Option<PathBuf>,
+    errors: Reporter,
 }
 
 impl Logger {
     pub fn new() -> Self {
-        let (sender, receiver) = channel();
-        thread::spawn(move || {
-            while let Ok(s) = receiver.recv() {
-                let (level, module, msg) = s;
-                let m1: String = module;
-                let m2: String = msg;
-                crate::log!(level, m1.as_str(), m2.as_str());
-            }
-        });
-        Logger {
-            sender,
+        let mut log = Logger {
+            queue: Self::spawn_queue(0, OVERFLOW::Block),
             fmthandle: FmtHandler::new(),
             filehandle: ("".to_string(), FHandler::new()),
             mutex: std::sync::Mutex::new(0),
@@ -92,9 +93,44 @@ impl Logger {
             // levelfmt: None,
             // timefmt: None,
             attrfmt: AttrFormat::new(),
+            config_path: None,
+            errors: Reporter::default(),
+        };
+        log.reset_options();
+        log
+    }
+
+    // Restores what a config file can set to the defaults, including module levels from RUST_LOG.
+    fn reset_options(&mut self) {
+        self.fmthandle = FmtHandler::new();
+        self.separator = "".to_string();
+        self.modmap = Trie::new();
+        self.fmap.clear();
+        self.levels = None;
+        for d in env_mod_directives() {
+            self.set_mod_level(d.module.as_deref().unwrap_or_default(), d.level);
         }
     }
 
+    fn spawn_queue(capacity: usize, overflow: OVERFLOW) -> Queue {
+        Queue::spawn(capacity, overflow, |receiver, dropped, closed| {
+            queue::run(
+                receiver,
+                dropped,
+                closed,
+                print,
+                |n| {
+                    let mut log = global();
+                    let s = log.fmt("tklog", LEVEL::Warn, "", 0, queue::dropped_message(n));
+                    if !s.is_empty() {
+                        log.print(LEVEL::Warn, "tklog", s.as_str());
+                    }
+                },
+                || global().write_due(),
+            );
+        })
+    }
+
     pub fn print(&mut self, level: LEVEL, module: &str, message: &str) {
         let mut console = String::new();
         let mut msg = String::new();
@@ -119,9 +155,11 @@ impl Logger {
                         }
                     }
                 }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self.filehandle.1.print(
+                if filename != "" || !lo.sinks.is_empty() {
+                    // an option without a file of its own writes to the default one
+                    if filename == "" || *filename == self.filehandle.0 {
+                        if let Err(e) = self.filehandle.1.print(
+                            level,
                             is_mod_console,
                             if is_mod_console {
                                 if is_consolefmt {
@@ -133,10 +171,13 @@ impl Logger {
                                 ""
                             },
                             if is_bodyfmt { msg.as_str() } else { message },
-                        );
+                        ) {
+                            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+                        }
                     } else {
                         if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm.print(
+                            if let Err(e) = fm.print(
+                                level,
                                 is_mod_console,
                                 if is_mod_console {
                                     if is_consolefmt {
@@ -148,9 +189,14 @@ impl Logger {
                                     ""
                                 },
                                 if is_bodyfmt { msg.as_str() } else { message },
-                            );
+                            ) {
+                                self.errors.report(FAILURE::WRITE, filename, e);
+                            }
                         }
                     }
+                    if let Err(e) = write_sinks(&lo.sinks, level, if is_bodyfmt { msg.as_str() } else { message }) {
+                        self.errors.report(FAILURE::WRITE, "", e);
+                    }
                     return;
                 }
             }
@@ -170,9 +216,11 @@ impl Logger {
                         }
                     }
                 }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self.filehandle.1.print(
+                if filename != "" || !lo.sinks.is_empty() {
+                    // an option without a file of its own writes to the default one
+                    if filename == "" || *filename == self.filehandle.0 {
+                        if let Err(e) = self.filehandle.1.print(
+                            level,
                             is_level_console,
                             if is_level_console {
                                 if is_consolefmt {
@@ -184,10 +232,13 @@ impl Logger {
                                 ""
                             },
                             if is_bodyfmt { msg.as_str() } else { message },
-                        );
+                        ) {
+                            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+                        }
                     } else {
                         if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm.print(
+                            if let Err(e) = fm.print(
+                                level,
                                 is_level_console,
                                 if is_level_console {
                                     if is_consolefmt {
@@ -199,9 +250,14 @@ impl Logger {
                                     ""
                                 },
                                 if is_bodyfmt { msg.as_str() } else { message },
-                            );
+                            ) {
+                                self.errors.report(FAILURE::WRITE, filename, e);
+                            }
                         }
                     }
+                    if let Err(e) = write_sinks(&lo.sinks, level, if is_bodyfmt { msg.as_str() } else { message }) {
+                        self.errors.report(FAILURE::WRITE, "", e);
+                    }
                     return;
                 }
             }
@@ -215,7 +271,8 @@ impl Logger {
                 console = f(level, message.to_string());
             }
         }
-        let _ = self.filehandle.1.print(
+        if let Err(e) = self.filehandle.1.print(
+            level,
             is_console,
             if is_console {
                 if is_consolefmt {
@@ -227,7 +284,9 @@ impl Logger {
                 ""
             },
             if is_bodyfmt { msg.as_str() } else { message },
-        );
+        ) {
+            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+        }
     }
 
     pub fn safeprint(&mut self, level: LEVEL, module: &str, message: &str) {
@@ -255,9 +314,11 @@ impl Logger {
                         }
                     }
                 }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self.filehandle.1.print(
+                if filename != "" || !lo.sinks.is_empty() {
+                    // an option without a file of its own writes to the default one
+                    if filename == "" || *filename == self.filehandle.0 {
+                        if let Err(e) = self.filehandle.1.print(
+                            level,
                             is_mod_console,
                             if is_mod_console {
                                 if is_consolefmt {
@@ -269,10 +330,13 @@ impl Logger {
                                 ""
                             },
                             if is_bodyfmt { msg.as_str() } else { message },
-                        );
+                        ) {
+                            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+                        }
                     } else {
                         if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm.print(
+                            if let Err(e) = fm.print(
+                                level,
                                 is_mod_console,
                                 if is_mod_console {
                                     if is_consolefmt {
@@ -284,9 +348,14 @@ impl Logger {
                                     ""
                                 },
                                 if is_bodyfmt { msg.as_str() } else { message },
-                            );
+                            ) {
+                                self.errors.report(FAILURE::WRITE, filename, e);
+                            }
                         }
                     }
+                    if let Err(e) = write_sinks(&lo.sinks, level, if is_bodyfmt { msg.as_str() } else { message }) {
+                        self.errors.report(FAILURE::WRITE, "", e);
+                    }
                     return;
                 }
             }
@@ -306,9 +375,11 @@ impl Logger {
                         }
                     }
                 }
-                if filename != "" {
-                    if *filename == self.filehandle.0 {
-                        let _ = self.filehandle.1.print(
+                if filename != "" || !lo.sinks.is_empty() {
+                    // an option without a file of its own writes to the default one
+                    if filename == "" || *filename == self.filehandle.0 {
+                        if let Err(e) = self.filehandle.1.print(
+                            level,
                             is_level_console,
                             if is_level_console {
                                 if is_consolefmt {
@@ -320,10 +391,13 @@ impl Logger {
                                 ""
                             },
                             if is_bodyfmt { msg.as_str() } else { message },
-                        );
+                        ) {
+                            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+                        }
                     } else {
                         if let Some(fm) = self.fmap.get_mut(filename) {
-                            let _ = fm.print(
+                            if let Err(e) = fm.print(
+                                level,
                                 is_level_console,
                                 if is_level_console {
                                     if is_consolefmt {
@@ -335,9 +409,14 @@ impl Logger {
                                     ""
                                 },
                                 if is_bodyfmt { msg.as_str() } else { message },
-                            );
+                            ) {
+                                self.errors.report(FAILURE::WRITE, filename, e);
+                            }
                         }
                     }
+                    if let Err(e) = write_sinks(&lo.sinks, level, if is_bodyfmt { msg.as_str() } else { message }) {
+                        self.errors.report(FAILURE::WRITE, "", e);
+                    }
                     return;
                 }
             }
@@ -351,7 +430,8 @@ impl Logger {
                 console = f(level, message.to_string());
             }
         }
-        let _ = self.filehandle.1.print(
+        if let Err(e) = self.filehandle.1.print(
+            level,
             is_console,
             if is_console {
                 if is_consolefmt {
@@ -363,11 +443,126 @@ impl Logger {
                 ""
             },
             if is_bodyfmt { msg.as_str() } else { message },
-        );
+        ) {
+            self.errors.report(FAILURE::WRITE, &self.filehandle.0, e);
+        }
     }
 
     pub fn log(&self, level: LEVEL, module: String, message: String) {
-        self.sender.send((level, module, message)).expect("send error");
+        self.queue.push(level, module, message);
+    }
+
+    /// Bounds the `PRINTMODE::DELAY` queue to `capacity` records (0 means unbounded)
+    /// and sets what happens to records arriving while it is full.
+    /// Records already queued are written before this returns.
+    pub fn set_queue(&mut self, capacity: usize, overflow: OVERFLOW) -> &mut Self {
+        let old = self.replace_queue(capacity, overflow);
+        wait_writer(&old, Message::Shutdown);
+        self
+    }
+
+    fn replace_queue(&mut self, capacity: usize, overflow: OVERFLOW) -> Queue {
+        let queue = Self::spawn_queue(capacity, overflow);
+        std::mem::replace(&mut self.queue, queue)
+    }
+
+    /// The number of records discarded by the overflow policy since the queue was set.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
+
+    /// Calls `handler` with each error the logger has no caller to return to: files it could not
+    /// open, records it could not write, and failed rotations, compressions and retention runs.
+    /// Write errors are reported while the logger is locked, so the handler must not log through it.
+    pub fn set_error_handler(&mut self, handler: impl Fn(&LogError) + Send + Sync + 'static) -> &mut Self {
+        self.errors.set_handler(Arc::new(handler));
+        self
+    }
+
+    /// The errors met since the logger was created, by kind.
+    pub fn errors(&self) -> ErrorStats {
+        self.errors.stats()
+    }
+
+    /// Blocks until every record queued in `PRINTMODE::DELAY` has been written,
+    /// then flushes the console, syncs the log files and flushes the sinks.
+    pub fn flush(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush);
+        self.flush_handlers()
+    }
+
+    /// Drains the queue like `flush`, then stops the background writer.
+    /// The logger switches to `PRINTMODE::PUNCTUAL`, so later records are still written.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        self.mode = PRINTMODE::PUNCTUAL;
+        wait_writer(&self.queue, Message::Shutdown);
+        self.flush_handlers()
+    }
+
+    fn flush_handlers(&mut self) -> io::Result<()> {
+        let mut r = self.filehandle.1.flush();
+        for fh in self.fmap.values_mut() {
+            if let Err(e) = fh.flush() {
+                r = Err(e);
+            }
+        }
+        for (lo, _) in self.modmap.values() {
+            if let Err(e) = flush_sinks(&lo.sinks) {
+                r = Err(e);
+            }
+        }
+        if let Some(levels) = &self.levels {
+            for (lo, _) in levels.iter().flatten() {
+                if let Err(e) = flush_sinks(&lo.sinks) {
+                    r = Err(e);
+                }
+            }
+        }
+        r
+    }
+
+    /// Writes the records queued in `PRINTMODE::DELAY`, then reopens every log file at its
+    /// configured path without renaming it. Call it after logrotate or another tool has moved the files away.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush);
+        self.each_file(FHandler::reopen)
+    }
+
+    /// Writes the records queued in `PRINTMODE::DELAY`, then rotates every log file at once,
+    /// renaming, compressing and pruning the backups as a scheduled rotation would.
+    pub fn rotate_now(&mut self) -> io::Result<()> {
+        wait_writer(&self.queue, Message::Flush);
+        self.each_file(FHandler::rotate_now)
+    }
+
+    // Writes the file buffers whose flush interval has passed; returns how long until the next may be due.
+    fn write_due(&mut self) -> Option<Duration> {
+        let mut next = self.filehandle.1.write_due();
+        for fh in self.fmap.values_mut() {
+            next = match (next, fh.write_due()) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        next
+    }
+
+    fn each_file(&mut self, f: fn(&mut FHandler) -> io::Result<()>) -> io::Result<()> {
+        let mut r = f(&mut self.filehandle.1);
+        for fh in self.fmap.values_mut() {
+            if let Err(e) = f(fh) {
+                r = Err(e);
+            }
+        }
+        r
+    }
+
+    /// Returns `target` when a module option is registered for it, otherwise `module`.
+    pub fn route_module<'a>(&mut self, target: &'a str, module: &'a str) -> &'a str {
+        if !target.is_empty() && target != module && self.modmap.len() > 0 && self.modmap.get(target).is_some() {
+            return target;
+        }
+        module
     }
 
     pub fn get_level(&mut self, module: &str) -> LEVEL {
@@ -404,16 +599,28 @@ impl Logger {
     }
 
     pub fn fmt(&mut self, module: &str, level: LEVEL, filename: &str, line: u32, message: String) -> String {
+        self.fmt_fields(module, level, filename, line, message, Vec::new())
+    }
+
+    /// Same as `fmt`, with the `key = value` fields given to the logging macros.
+    pub fn fmt_fields(&mut self, module: &str, level: LEVEL, filename: &str, line: u32, message: String, fields: Vec<(String, FieldValue)>) -> String {
+        self.fmt_record(module, module, level, filename, line, message, fields)
+    }
+
+    /// Formats a record that carries a `log` target besides its module path.
+    /// Module and level options are looked up by the key returned from `route_module`.
+    pub fn fmt_record(&mut self, target: &str, module: &str, level: LEVEL, filename: &str, line: u32, message: String, fields: Vec<(String, FieldValue)>) -> String {
         if let Some(ch) = &self.custom_handler {
-            if !ch(&LogContext { level: level, filename: filename.to_string(), line: line, log_body: message.clone(), modname: module.to_string() }) {
+            if !ch(&LogContext { level: level, filename: filename.to_string(), line: line, log_body: message.clone(), modname: module.to_string(), fields: fields.clone(), target: target.to_string() }) {
                 return String::new();
             }
         }
+        let route = self.route_module(target, module);
 
         let mut fmat = self.fmthandle.get_format();
         let mut formatter = self.fmthandle.get_formatter();
-        if module != "" && self.modmap.len() > 0 {
-            if let Some(mm) = self.modmap.get(module) {
+        if route != "" && self.modmap.len() > 0 {
+            if let Some(mm) = self.modmap.get(route) {
                 let (lo, _) = mm;
                 if let Some(v) = lo.format {
                     fmat = v;
@@ -436,7 +643,8 @@ impl Logger {
             }
         }
 
-        log_fmt(self.attrfmt.levelfmt.as_ref(), self.attrfmt.timefmt.as_ref(), fmat, formatter, level, filename, line, message.as_str())
+        let rec = FmtRecord { level, module, filename, line, msg: message.as_str(), fields: &fields };
+        log_fmt(self.attrfmt.levelfmt.as_ref(), self.attrfmt.timefmt.as_ref(), fmat, formatter, &rec)
     }
 
     pub fn set_printmode(&mut self, mode: PRINTMODE) -> &mut Self {
@@ -449,6 +657,26 @@ impl Logger {
         self
     }
 
+    /// Sets the level of `module` and its submodules.
+    /// An option already matching the module, such as its log file, is kept.
+    pub fn set_mod_level(&mut self, module: &str, level: LEVEL) -> &mut Self {
+        let (mut lo, filename) = self.modmap.get(module).cloned().unwrap_or_default();
+        lo.level = Some(level);
+        self.modmap.insert(module, (lo, filename));
+        self
+    }
+
+    /// Applies `RUST_LOG` style directives, see [`crate::parse_directives`].
+    pub fn set_directives(&mut self, directives: &[Directive]) -> &mut Self {
+        for d in directives {
+            match &d.module {
+                Some(module) => self.set_mod_level(module, d.level),
+                None => self.set_level(d.level),
+            };
+        }
+        self
+    }
+
     pub fn set_console(&mut self, console: bool) -> &mut Self {
         self.fmthandle.set_console(console);
         self
@@ -466,31 +694,70 @@ impl Logger {
         self
     }
 
+    /// When the file cannot be opened, the error goes to the error handler and records keep going
+    /// where they went before; the `try_` variant returns the error instead.
     pub fn set_cutmode_by_size(&mut self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> &mut Self {
+        if let Err(e) = self.try_set_cutmode_by_size(filename, maxsize, maxbackups, compress) {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
+        self
+    }
+
+    pub fn try_set_cutmode_by_size(&mut self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
         let fsm = FileOptionType::new(crate::CUTMODE::SIZE, MODE::DAY, filename, maxsize, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(fsm));
+        let fh = self.try_open(Box::new(fsm))?;
         self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_file_handler(fh.unwrap());
-        self
+        self.filehandle.1.set_file_handler(fh);
+        Ok(self)
     }
 
     pub fn set_cutmode_by_time(&mut self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> &mut Self {
+        if let Err(e) = self.try_set_cutmode_by_time(filename, mode, maxbackups, compress) {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
+        self
+    }
+
+    pub fn try_set_cutmode_by_time(&mut self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
         let ftm = FileOptionType::new(crate::CUTMODE::TIME, mode, filename, 0, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(ftm));
+        let fh = self.try_open(Box::new(ftm))?;
         self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_file_handler(fh.unwrap());
-        self
+        self.filehandle.1.set_file_handler(fh);
+        Ok(self)
     }
 
     pub fn set_cutmode_by_mixed(&mut self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> &mut Self {
+        if let Err(e) = self.try_set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress) {
+            self.errors.report(FAILURE::OPEN, filename, e);
+        }
+        self
+    }
+
+    pub fn try_set_cutmode_by_mixed(&mut self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&mut Self> {
         let ftm = FileOptionType::new(crate::CUTMODE::MIXED, mode, filename, maxsize, maxbackups, compress);
-        let fh = FileHandler::new(Box::new(ftm));
+        let fh = self.try_open(Box::new(ftm))?;
         self.filehandle.0 = filename.to_string();
-        self.filehandle.1.set_file_handler(fh.unwrap());
-        self
+        self.filehandle.1.set_file_handler(fh);
+        Ok(self)
+    }
+
+    pub fn set_option(&mut self, option: impl Into<SinkOption>) -> &mut Self {
+        let (mut option, sinks, _) = option.into().into_parts();
+        let fh = option.fileoption.take().and_then(|fo| self.open(fo));
+        self.apply_option(option, sinks, fh)
     }
 
-    pub fn set_option(&mut self, option: LogOption) -> &mut Self {
+    /// Like `set_option`, but returns the error when the log file cannot be opened, leaving the logger unchanged.
+    pub fn try_set_option(&mut self, option: impl Into<SinkOption>) -> io::Result<&mut Self> {
+        let (mut option, sinks, _) = option.into().into_parts();
+        let fh = match option.fileoption.take() {
+            Some(fo) => Some(self.try_open(fo)?),
+            None => None,
+        };
+        Ok(self.apply_option(option, sinks, fh))
+    }
+
+    fn apply_option(&mut self, option: LogOption, sinks: Vec<SinkRef>, fh: Option<FileHandler>) -> &mut Self {
         if let Some(v) = option.console {
             self.fmthandle.set_console(v);
         }
@@ -503,35 +770,47 @@ impl Logger {
         if let Some(v) = option.level {
             self.fmthandle.set_level(v);
         }
-        if let Some(v) = option.fileoption {
-            match FileHandler::new(v) {
-                Ok(f) => {
-                    self.filehandle.0 = f.get_file_name();
-                    self.filehandle.1.set_file_handler(f);
-                }
-                Err(_) => {}
-            }
+        for sink in sinks {
+            self.filehandle.1.add_sink(sink);
+        }
+        if let Some(f) = fh {
+            self.filehandle.0 = f.get_file_name();
+            self.filehandle.1.set_file_handler(f);
         }
 
         self
     }
 
-    pub fn set_mod_option(&mut self, module: &str, option: LogOption) -> &mut Self {
+    fn try_open(&self, fileoption: Box<dyn FileOption>) -> io::Result<FileHandler> {
+        FileHandler::with_errors(fileoption, self.errors.clone())
+    }
+
+    // Opens a log file, reporting the error when it cannot.
+    fn open(&self, fileoption: Box<dyn FileOption>) -> Option<FileHandler> {
+        let filename = fileoption.filename();
+        match self.try_open(fileoption) {
+            Ok(f) => Some(f),
+            Err(e) => {
+                self.errors.report(FAILURE::OPEN, filename, e);
+                None
+            }
+        }
+    }
+
+    pub fn set_mod_option(&mut self, module: &str, option: impl Into<SinkOption>) -> &mut Self {
+        let (option, sinks, async_sinks) = option.into().into_parts();
         let mut filename = "".to_string();
         if let Some(v) = option.fileoption {
-            match FileHandler::new(v) {
-                Ok(f) => {
-                    filename = f.get_file_name();
-                    if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
-                        let mut fhandler = FHandler::new();
-                        fhandler.set_file_handler(f);
-                        self.fmap.insert(filename.clone(), fhandler);
-                    }
+            if let Some(f) = self.open(v) {
+                filename = f.get_file_name();
+                if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
+                    let mut fhandler = FHandler::new();
+                    fhandler.set_file_handler(f);
+                    self.fmap.insert(filename.clone(), fhandler);
                 }
-                Err(_) => {}
             }
         }
-        self.modmap.insert(module, (LogOptionConst { level: option.level, format: option.format, formatter: option.formatter, console: option.console }, filename.clone()));
+        self.modmap.insert(module, (LogOptionConst { level: option.level, format: option.format, formatter: option.formatter, console: option.console, sinks, async_sinks }, filename.clone()));
         self
     }
 
@@ -543,19 +822,16 @@ impl Logger {
     pub fn set_level_option(&mut self, level: LEVEL, option: &dyn OptionTrait) -> &mut Self {
         let mut filename = "".to_string();
         if let Some(v) = option.get_fileoption() {
-            match FileHandler::new(v) {
-                Ok(f) => {
-                    filename = f.get_file_name();
-                    if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
-                        let mut fhandler = FHandler::new();
-                        fhandler.set_file_handler(f);
-                        self.fmap.insert(filename.clone(), fhandler);
-                    }
+            if let Some(f) = self.open(v) {
+                filename = f.get_file_name();
+                if filename != self.filehandle.0 && !self.fmap.contains_key(&filename) {
+                    let mut fhandler = FHandler::new();
+                    fhandler.set_file_handler(f);
+                    self.fmap.insert(filename.clone(), fhandler);
                 }
-                Err(_) => {}
             }
         }
-        let lo = LogOption { level: None, format: option.get_format(), formatter: option.get_formatter(), console: option.get_console(), fileoption: option.get_fileoption() };
+        let lo = LogOptionConst { level: None, format: option.get_format(), formatter: option.get_formatter(), console: option.get_console(), sinks: option.get_sinks(), async_sinks: option.get_async_sinks() };
 
         if self.levels.is_none() {
             self.levels = Some(std::array::from_fn(|_| None));
@@ -573,6 +849,60 @@ impl Logger {
         self
     }
 
+    /// Loads a `.toml` or `.json` configuration file, see [`crate::config`].
+    /// An invalid file returns an error and leaves the logger unchanged.
+    pub fn load_config(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, ConfigError> {
+        let config = Config::from_file(path.as_ref())?;
+        self.apply_config(&config)?;
+        self.config_path = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn apply_config(&mut self, config: &Config) -> Result<&mut Self, ConfigError> {
+        let settings = config.settings()?;
+        Ok(self.apply_settings(settings))
+    }
+
+    /// Reads the file given to `load_config` again and swaps in its configuration, see `reload_config`.
+    pub fn reload(&mut self) -> Result<&mut Self, ConfigError> {
+        let config = Config::from_file(self.config_path()?)?;
+        self.reload_config(&config)
+    }
+
+    /// Replaces the configuration with `config`: levels, formats, module options and level options
+    /// missing from it return to their defaults instead of keeping their current values.
+    /// Queued records, the custom handler, attribute formats and sinks of the main file are kept.
+    pub fn reload_config(&mut self, config: &Config) -> Result<&mut Self, ConfigError> {
+        let settings = config.settings()?;
+        self.reset_options();
+        Ok(self.apply_settings(settings))
+    }
+
+    fn config_path(&self) -> Result<PathBuf, ConfigError> {
+        self.config_path.clone().ok_or_else(|| ConfigError::Invalid("no config file has been loaded".to_string()))
+    }
+
+    fn apply_settings(&mut self, settings: Settings) -> &mut Self {
+        // before the file handlers below compute their first rotation
+        if let Some(timezone) = settings.timezone {
+            crate::set_timezone(timezone);
+        }
+        if let Some(mode) = settings.printmode {
+            self.set_printmode(mode);
+        }
+        if let Some(separator) = &settings.separator {
+            self.set_separator(separator);
+        }
+        self.set_option(settings.option);
+        for (module, option) in settings.modules {
+            self.set_mod_option(&module, option);
+        }
+        for (level, option) in settings.levels {
+            self.set_level_option(level, &option);
+        }
+        self.set_directives(&settings.directives)
+    }
+
     pub fn get_separator(&self) -> String {
         self.separator.clone()
     }
@@ -606,102 +936,197 @@ impl Log {
         Log {}
     }
     pub fn set_printmode(&self, mode: PRINTMODE) -> &Self {
-        unsafe {
-            synclog.set_printmode(mode);
-        }
+        global().set_printmode(mode);
         self
     }
 
     pub fn set_level(&self, level: LEVEL) -> &Self {
-        unsafe {
-            synclog.set_level(level);
-        }
+        global().set_level(level);
+        self
+    }
+
+    pub fn set_mod_level(&self, module: &str, level: LEVEL) -> &Self {
+        global().set_mod_level(module, level);
+        self
+    }
+
+    pub fn set_directives(&self, directives: &[Directive]) -> &Self {
+        global().set_directives(directives);
         self
     }
 
     pub fn set_console(&self, console: bool) -> &Self {
-        unsafe {
-            synclog.set_console(console);
-        }
+        global().set_console(console);
         self
     }
 
     /**Format::LevelFlag | Format::Date | Format::Time | Format::ShortFileName; */
     pub fn set_format(&self, format: u8) -> &Self {
-        unsafe {
-            synclog.set_format(format);
-        }
+        global().set_format(format);
         self
     }
 
     /** default: "{level}{time} {file}:{message}\n" */
     pub fn set_formatter(&self, formatter: &str) -> &Self {
-        unsafe {
-            synclog.set_formatter(formatter);
-        }
+        global().set_formatter(formatter);
         self
     }
 
     pub fn set_cutmode_by_size(&self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            synclog.set_cutmode_by_size(filename, maxsize, maxbackups, compress);
-        }
+        global().set_cutmode_by_size(filename, maxsize, maxbackups, compress);
         self
     }
 
     pub fn set_cutmode_by_time(&self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            synclog.set_cutmode_by_time(filename, mode, maxbackups, compress);
-        }
+        global().set_cutmode_by_time(filename, mode, maxbackups, compress);
         self
     }
 
     pub fn set_cutmode_by_mixed(&self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> &Self {
-        unsafe {
-            synclog.set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress);
-        }
+        global().set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress);
         self
     }
 
-    pub fn set_option(&self, option: LogOption) -> &Self {
-        unsafe {
-            synclog.set_option(option);
-        }
+    pub fn try_set_cutmode_by_size(&self, filename: &str, maxsize: u64, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        global().try_set_cutmode_by_size(filename, maxsize, maxbackups, compress)?;
+        Ok(self)
+    }
+
+    pub fn try_set_cutmode_by_time(&self, filename: &str, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        global().try_set_cutmode_by_time(filename, mode, maxbackups, compress)?;
+        Ok(self)
+    }
+
+    pub fn try_set_cutmode_by_mixed(&self, filename: &str, maxsize: u64, mode: MODE, maxbackups: u32, compress: bool) -> io::Result<&Self> {
+        global().try_set_cutmode_by_mixed(filename, maxsize, mode, maxbackups, compress)?;
+        Ok(self)
+    }
+
+    pub fn set_option(&self, option: impl Into<SinkOption>) -> &Self {
+        global().set_option(option);
         self
     }
 
-    pub fn set_mod_option(&self, module: &str, option: LogOption) -> &Self {
-        unsafe {
-            synclog.set_mod_option(module, option);
-        }
+    pub fn try_set_option(&self, option: impl Into<SinkOption>) -> io::Result<&Self> {
+        global().try_set_option(option)?;
+        Ok(self)
+    }
+
+    pub fn set_mod_option(&self, module: &str, option: impl Into<SinkOption>) -> &Self {
+        global().set_mod_option(module, option);
         self
     }
 
     pub fn set_level_option(&self, level: LEVEL, option: impl OptionTrait) -> &Self {
-        unsafe {
-            synclog.set_level_option(level, &option);
-        }
+        global().set_level_option(level, &option);
         self
     }
 
     pub fn set_custom_handler(&self, handler: fn(&LogContext) -> bool) -> &Self {
-        unsafe {
-            synclog.set_custom_handler(handler);
-        }
+        global().set_custom_handler(handler);
         self
     }
 
     pub fn set_separator(&self, separator: &str) -> &Self {
-        unsafe {
-            synclog.set_separator(separator);
-        }
+        global().set_separator(separator);
         self
     }
 
-    fn is_file_line(&self, level: LEVEL, module: &str) -> bool {
-        unsafe {
-            return synclog.is_file_line(level, module);
-        }
+    pub fn load_config(&self, path: impl AsRef<Path>) -> Result<&Self, ConfigError> {
+        let config = Config::from_file(path.as_ref())?;
+        let mut log = global();
+        log.apply_config(&config)?;
+        log.config_path = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn apply_config(&self, config: &Config) -> Result<&Self, ConfigError> {
+        global().apply_config(config)?;
+        Ok(self)
+    }
+
+    pub fn reload(&self) -> Result<&Self, ConfigError> {
+        let path = global().config_path()?;
+        let config = Config::from_file(path)?;
+        self.reload_config(&config)
+    }
+
+    pub fn reload_config(&self, config: &Config) -> Result<&Self, ConfigError> {
+        global().reload_config(config)?;
+        Ok(self)
+    }
+
+    /// Loads `path`, then polls it every `interval` and reloads it when it changes.
+    /// A reload that fails is logged and the previous configuration stays in place.
+    /// Watching another file, or the same one again, stops the previous watch.
+    pub fn watch_config(&self, path: impl AsRef<Path>, interval: Duration) -> Result<&Self, ConfigError> {
+        self.load_config(path.as_ref())?;
+        *WATCHER.lock().unwrap_or_else(|e| e.into_inner()) = Some(config::watch(path, interval, || report_reload(Log::reload(&Log))));
+        Ok(self)
+    }
+
+    /// Reloads the file given to `load_config` whenever the process receives SIGHUP.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(&self) -> io::Result<&Self> {
+        config::on_sighup(|| report_reload(Log::reload(&Log)))?;
+        Ok(self)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush);
+        global().flush_handlers()
+    }
+
+    pub fn reopen(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush);
+        global().each_file(FHandler::reopen)
+    }
+
+    pub fn rotate_now(&self) -> io::Result<()> {
+        let queue = global().queue.clone();
+        wait_writer(&queue, Message::Flush);
+        global().each_file(FHandler::rotate_now)
+    }
+
+    /// Reopens the log files whenever the process receives `signal`, e.g. from a logrotate
+    /// `postrotate` script. Pass `SignalKind::hangup()` only when `reload_on_sighup` is not used.
+    #[cfg(unix)]
+    pub fn reopen_on_signal(&self, signal: SignalKind) -> io::Result<&Self> {
+        config::on_signal(signal, || {
+            let _ = Log::reopen(&Log);
+        })?;
+        Ok(self)
+    }
+
+    pub fn set_queue(&self, capacity: usize, overflow: OVERFLOW) -> &Self {
+        let old = global().replace_queue(capacity, overflow);
+        wait_writer(&old, Message::Shutdown);
+        self
+    }
+
+    pub fn dropped(&self) -> u64 {
+        global().dropped()
+    }
+
+    pub fn set_error_handler(&self, handler: impl Fn(&LogError) + Send + Sync + 'static) -> &Self {
+        global().set_error_handler(handler);
+        self
+    }
+
+    pub fn errors(&self) -> ErrorStats {
+        global().errors()
+    }
+
+    pub fn shutdown(&self) -> io::Result<()> {
+        let queue = {
+            let mut log = global();
+            log.mode = PRINTMODE::PUNCTUAL;
+            log.queue.clone()
+        };
+        wait_writer(&queue, Message::Shutdown);
+        global().flush_handlers()
     }
 
     pub fn uselog(&self) -> &Self {
@@ -728,9 +1153,7 @@ impl Log {
     where
         F: FnMut(&mut AttrFormat) + Send + Sync + 'static,
     {
-        unsafe {
-            synclog.set_attr_format(f);
-        }
+        global().set_attr_format(f);
     }
 }
 
@@ -740,37 +1163,78 @@ impl log::Log for Log {
     }
     fn log(&self, record: &log::Record) {
         let level = l2tk(record.level());
-        let mut module = "";
-        if let Some(m) = record.module_path() {
-            module = m;
-            unsafe {
-                if synclog.get_level(module) > level {
-                    return;
-                }
+        let module = record.module_path().unwrap_or("");
+        let target = record.target();
+        {
+            let mut log = global();
+            let route = log.route_module(target, module);
+            if log.get_level(route) > level {
+                return;
             }
         }
-        let args = record.args();
-        let mut file = "";
-        let mut line: u32 = 0;
-        if self.is_file_line(level, module) {
-            line = record.line().unwrap_or(0);
-            file = record.file().unwrap_or("");
-        }
-        unsafe {
-            if synclog.mode == PRINTMODE::DELAY {
-                let s = synclog.fmt(module, level, file, line, arguments_to_string(args));
-                if !s.is_empty() {
-                    synclog.log(level, module.to_string(), s);
-                }
-            } else {
-                let s = synclog.fmt(module, level, file, line, arguments_to_string(args));
-                if !s.is_empty() {
-                    synclog.safeprint(level, module, s.as_str());
-                }
-            }
+        submit(target, module, level, record.file().unwrap_or(""), record.line().unwrap_or(0), vec![arguments_to_string(record.args())], record_fields(record));
+    }
+    fn flush(&self) {
+        let _ = Log::flush(self);
+    }
+}
+
+fn report_reload(result: Result<&Log, ConfigError>) {
+    if let Err(e) = result {
+        let mut log = global();
+        let s = log.fmt("tklog", LEVEL::Error, "", 0, format!("tklog failed to reload config: {}", e));
+        if !s.is_empty() {
+            log.print(LEVEL::Error, "tklog", s.as_str());
         }
     }
-    fn flush(&self) {}
+}
+
+// The file `watch_config` polls for the global logger.
+static WATCHER: Mutex<Option<config::Watcher>> = Mutex::new(None);
+
+fn global() -> MutexGuard<'static, Logger> {
+    synclog.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Sends a flush or shutdown marker and blocks until the writer reaches it.
+fn wait_writer(queue: &Queue, marker: fn(Ack) -> Message) {
+    let (ack, done) = channel();
+    if queue.control(marker(Box::new(move || {
+        let _ = ack.send(());
+    }))) {
+        let _ = done.recv();
+    }
+}
+
+#[doc(hidden)]
+pub fn enabled(level: LEVEL, module: &str) -> bool {
+    global().get_level(module) <= level
+}
+
+/// Formats a record with the global logger, then queues it in `PRINTMODE::DELAY` or prints it.
+/// The lock is released before queueing, so a full queue never blocks the writer.
+#[doc(hidden)]
+pub fn submit(target: &str, module: &str, level: LEVEL, filename: &str, line: u32, args: Vec<String>, fields: Vec<(String, FieldValue)>) {
+    let mut log = global();
+    let route = log.route_module(target, module);
+    let (filename, line) = if log.is_file_line(level, route) { (filename, line) } else { ("", 0) };
+    let message = args.join(log.separator.as_str());
+    let s = log.fmt_record(target, module, level, filename, line, message, fields);
+    if s.is_empty() {
+        return;
+    }
+    if log.mode == PRINTMODE::DELAY {
+        let queue = log.queue.clone();
+        drop(log);
+        queue.push(level, route.to_string(), s);
+    } else {
+        log.safeprint(level, route, s.as_str());
+    }
+}
+
+#[doc(hidden)]
+pub fn print(level: LEVEL, module: &str, message: &str) {
+    global().print(level, module, message);
 }
 
 #[macro_export]
@@ -779,15 +1243,19 @@ macro_rules! log {
         let level: LEVEL = $level;
         let msg: &str = $msg;
         let module: &str = $module;
-        unsafe {
-            synclog.print(level, module, msg);
-        }
+        global().print(level, module, msg);
     };
 }
 
 #[macro_export]
 macro_rules! trace {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Trace, $($arg),*);
     };
@@ -796,6 +1264,12 @@ macro_rules! trace {
 #[macro_export]
 macro_rules! debug {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Debug, $($arg),*);
     };
@@ -804,6 +1278,12 @@ macro_rules! debug {
 #[macro_export]
 macro_rules! info {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Info, $($arg),*);
     };
@@ -812,6 +1292,12 @@ macro_rules! info {
 #[macro_export]
 macro_rules! warn {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Warn, $($arg),*);
     };
@@ -820,6 +1306,12 @@ macro_rules! warn {
 #[macro_export]
 macro_rules! error {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Error, $($arg),*);
     };
@@ -828,6 +1320,12 @@ macro_rules! error {
 #[macro_export]
 macro_rules! fatal {
     () => {};
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::log_common!($crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::log_common!($crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], );
+    };
     ($($arg:expr),*) => {
         $crate::log_common!($crate::LEVEL::Fatal, $($arg),*);
     };
@@ -835,31 +1333,19 @@ macro_rules! fatal {
 
 #[macro_export]
 macro_rules! log_common {
-    ($level:expr, $($arg:expr),*) => {
-        unsafe {
+    ($level:expr, [$($key:ident $(:$capture:tt)? = $value:expr),*], $($arg:expr),*) => {
+        {
+            let level: $crate::LEVEL = $level;
             let module = module_path!();
-            if $crate::tklog::synclog.get_level(module) <= $level {
+            if $crate::sync::enabled(level, module) {
                 let formatted_args: Vec<String> = vec![$(format!("{}", $arg)),*];
-                let mut file = "";
-                let mut line = 0;
-                if $crate::tklog::synclog.is_file_line($level,module) {
-                    file = file!();
-                    line = line!();
-                }
-                let msg: String = formatted_args.join($crate::tklog::synclog.get_separator().as_str());
-                if  $crate::tklog::synclog.mode==$crate::PRINTMODE::DELAY {
-                    let s = $crate::tklog::synclog.fmt(module,$level, file, line, msg);
-                    if !s.is_empty(){
-                        $crate::tklog::synclog.log($level,module.to_string(),s);
-                    }
-                }else {
-                    let s = $crate::tklog::synclog.fmt(module,$level, file, line, msg);
-                    if !s.is_empty(){
-                        $crate::tklog::synclog.safeprint($level,module,s.as_str());
-                    }
-                }
+                let fields: Vec<(String, $crate::FieldValue)> = vec![$((stringify!($key).to_string(), $crate::field_value!($($capture)? $value))),*];
+                $crate::sync::submit(module, module, level, file!(), line!(), formatted_args, fields);
             }
         }
     };
+    ($level:expr, $($arg:expr),*) => {
+        $crate::log_common!($level, [], $($arg),*);
+    };
     () => {};
 }
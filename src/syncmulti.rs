@@ -18,6 +18,12 @@
 
 #[macro_export]
 macro_rules! traces {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Trace, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Trace, $($arg),*);
     };
@@ -27,6 +33,12 @@ macro_rules! traces {
 //Debug log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! debugs {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Debug, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Debug, $($arg),*);
     };
@@ -36,6 +48,12 @@ macro_rules! debugs {
 //Info log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! infos {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Info, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Info, $($arg),*);
     };
@@ -45,6 +63,12 @@ macro_rules! infos {
 // Error log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! warns {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Warn, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Warn, $($arg),*);
     };
@@ -54,6 +78,12 @@ macro_rules! warns {
 // Error log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! errors {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Error, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Error, $($arg),*);
     };
@@ -63,6 +93,12 @@ macro_rules! errors {
 // Fatal log macro, call secondary macro processing logic
 #[macro_export]
 macro_rules! fatals {
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ ; $($arg:expr),*) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], $($arg),*);
+    };
+    ($logger:expr, $($key:ident $(:$capture:tt)? = $value:expr),+ $(,)?) => {
+        $crate::logs_common!($logger, $crate::LEVEL::Fatal, [$($key $(:$capture)? = $value),+], );
+    };
     ($logger:expr, $($arg:expr),+) => {
         $crate::logs_common!($logger, $crate::LEVEL::Fatal, $($arg),*);
     };
@@ -73,7 +109,7 @@ macro_rules! fatals {
 macro_rules! formats {
     ($logger:expr, $level:expr, $($arg:expr),*) => {
         let level:$crate::LEVEL = $level;
-        unsafe {
+        {
             let log:&mut Arc<Mutex<tklog::sync::Logger>> = $logger;
             let mut logger  = log.lock().unwrap();
             let module = module_path!();
@@ -96,8 +132,8 @@ macro_rules! formats {
 
 #[macro_export]
 macro_rules! logs_common {
-    ($logger:expr, $level:expr, $($arg:expr),*) => {
-        unsafe {
+    ($logger:expr, $level:expr, [$($key:ident $(:$capture:tt)? = $value:expr),*], $($arg:expr),*) => {
+        {
             let  log:&mut Arc<Mutex<tklog::sync::Logger>> = $logger;
             let mut logger  = log.lock().unwrap();
             let module = module_path!();
@@ -110,12 +146,16 @@ macro_rules! logs_common {
                     line = line!();
                 }
                 let msg: String = formatted_args.join(logger.get_separator().as_str());
-                let ss = logger.fmt(module,$level, file, line, msg);
+                let fields: Vec<(String, $crate::FieldValue)> = vec![$((stringify!($key).to_string(), $crate::field_value!($($capture)? $value))),*];
+                let ss = logger.fmt_fields(module,$level, file, line, msg, fields);
                 if !ss.is_empty(){
                     logger.print($level,module, ss.as_str());
                 }
             }
         }
     };
+    ($logger:expr, $level:expr, $($arg:expr),*) => {
+        $crate::logs_common!($logger, $level, [], $($arg),*);
+    };
     () => {};
 }
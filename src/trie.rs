@@ -57,6 +57,19 @@ impl<V: Clone> Trie<V> {
         }
         node.module = Some(module);
         self.count += 1;
+        self.cache.clear();
+    }
+
+    pub fn values(&self) -> Vec<&V> {
+        let mut values = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if let Some(ref module) = node.module {
+                values.push(module);
+            }
+            stack.extend(node.children.values());
+        }
+        values
     }
 
     pub fn get(&mut self, input: &str) -> Option<&V> {
@@ -0,0 +1,1380 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tklog::{infos, sync::Logger, FieldValue, Format, LevelOption, LEVEL};
+
+#[test]
+fn testjson() {
+    let mut log = Logger::new();
+    log.set_format(Format::LevelFlag | Format::ShortFileName | Format::Json);
+    let s = log.fmt("app::db", LEVEL::Info, "src/db.rs", 12, "say \"hi\"\n\tbye".to_string());
+    assert_eq!(s, "{\"level\":\"INFO\",\"module\":\"app::db\",\"file\":\"db.rs\",\"line\":12,\"message\":\"say \\\"hi\\\"\\n\\tbye\"}\n");
+
+    log.set_format(Format::Json);
+    let s = log.fmt("app", LEVEL::Warn, "", 0, "plain".to_string());
+    assert_eq!(s, "{\"module\":\"app\",\"message\":\"plain\"}\n");
+}
+
+#[test]
+fn testjsonlevel() {
+    let mut log = Logger::new();
+    log.set_console(true).set_level(LEVEL::Trace).set_cutmode_by_size("030json.log", 1 << 20, 0, false);
+    log.set_level_option(LEVEL::Error, &LevelOption { format: Some(Format::LevelFlag | Format::Date | Format::Time | Format::LongFileName | Format::Json), formatter: None });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "info!", "text line");
+    tklog::errors!(log, "error!", "json line");
+    thread::sleep(Duration::from_secs(1))
+}
+
+#[test]
+fn testfields() {
+    let mut log = Logger::new();
+    log.set_format(Format::Nano);
+    let fields = vec![("user_id".to_string(), FieldValue::U64(42)), ("path".to_string(), FieldValue::Str("/a b".to_string())), ("ok".to_string(), FieldValue::Bool(true))];
+    let s = log.fmt_fields("app", LEVEL::Info, "", 0, "request done".to_string(), fields.clone());
+    assert_eq!(s, "request done user_id=42 path=\"/a b\" ok=true");
+
+    log.set_format(Format::LevelFlag | Format::Json);
+    let s = log.fmt_fields("app", LEVEL::Info, "", 0, "request done".to_string(), fields);
+    assert_eq!(s, "{\"level\":\"INFO\",\"module\":\"app\",\"message\":\"request done\",\"user_id\":42,\"path\":\"/a b\",\"ok\":true}\n");
+}
+
+#[test]
+fn testfieldsreserved() {
+    let mut log = Logger::new();
+    log.set_format(Format::Json);
+    let fields = vec![("message".to_string(), FieldValue::Str("shadow".to_string())), ("level".to_string(), FieldValue::U64(1)), ("module_name".to_string(), FieldValue::Bool(true))];
+    let s = log.fmt_fields("app", LEVEL::Info, "", 0, "request done".to_string(), fields);
+    assert_eq!(s, "{\"module\":\"app\",\"message\":\"request done\",\"fields.message\":\"shadow\",\"fields.level\":1,\"module_name\":true}\n");
+}
+
+#[test]
+fn testfieldsmacro() {
+    let mut log = Logger::new();
+    log.set_console(true).set_level(LEVEL::Trace).set_cutmode_by_size("030fields.log", 1 << 20, 0, false);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    let latency = 1.5;
+    infos!(log, user_id = 7, request_id = "req-1", latency = latency; "request done", 200);
+    infos!(log, user_id = 7, peer:% = "127.0.0.1", tags:? = vec!["a", "b"]);
+    tklog::info!(user_id = 7, name = String::from("tk"); "global logger");
+    tklog::debug!(user_id = 8);
+    thread::sleep(Duration::from_secs(1))
+}
+
+#[tokio::test]
+async fn testasyncfields() {
+    tklog::ASYNC_LOG.set_console(true).set_level(LEVEL::Trace);
+    tklog::async_info!(user_id = 9, elapsed_ms = 12u64; "async request done");
+    tklog::async_warn!(retry = true);
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+}
+
+#[test]
+fn testlogtargetkv() {
+    let _ = std::fs::remove_file("030audit.log");
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_format(Format::LevelFlag).set_fileoption(tklog::handle::FileSizeMode::new("030audit.log", 1 << 20, 0, false));
+    tklog::LOG.set_mod_option("audit", lo).uselog();
+    log::info!(target: "audit", user_id = 5, admin = true; "login");
+    log::info!("not routed to audit");
+    log::logger().flush();
+    let s = std::fs::read_to_string("030audit.log").unwrap();
+    assert_eq!(s, "[INFO] login user_id=5 admin=true\n");
+}
+
+struct VecSink(Arc<Mutex<Vec<String>>>);
+
+impl tklog::handle::Sink for VecSink {
+    fn write(&mut self, _: LEVEL, s: &str) -> std::io::Result<()> {
+        self.0.lock().unwrap().push(s.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn testsink() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let _ = std::fs::remove_file("030sink.log");
+    let mut log = Logger::new();
+    log.set_console(false).set_level(LEVEL::Trace).set_format(Format::Nano);
+    log.set_option(tklog::LogOption { fileoption: Some(Box::new(tklog::handle::FileSizeMode::new("030sink.log", 1 << 30, 0, false))), ..Default::default() }.with_sink(VecSink(lines.clone())));
+    log.set_level_option(LEVEL::Error, &tklog::LogOption::new().with_sink(VecSink(errors.clone())));
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "to default sink");
+    tklog::errors!(log, "to level sink");
+    assert_eq!(*errors.lock().unwrap(), vec!["to level sink".to_string()]);
+    // a level option without a file of its own also writes to the default file and sinks
+    assert_eq!(*lines.lock().unwrap(), vec!["to default sink".to_string(), "to level sink".to_string()]);
+    assert_eq!(std::fs::read_to_string("030sink.log").unwrap(), "to default sinkto level sink");
+}
+
+struct AsyncVecSink(Arc<Mutex<Vec<String>>>);
+
+impl tklog::handle::AsyncSink for AsyncVecSink {
+    fn write<'a>(&'a mut self, _: LEVEL, s: &'a str) -> tklog::handle::SinkFuture<'a> {
+        Box::pin(async move {
+            self.0.lock().unwrap().push(s.to_string());
+            Ok(())
+        })
+    }
+}
+
+#[tokio::test]
+async fn testasyncsink() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let _ = std::fs::remove_file("030asyncsink.log");
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_level(LEVEL::Trace).set_format(Format::Nano).set_cutmode_by_size("030asyncsink.log", 1 << 30, 0, false).await;
+    log.set_mod_option("test_0_3_0", tklog::LogOption::new().with_async_sink(AsyncVecSink(lines.clone()))).await;
+    let mut logger = Arc::new(tokio::sync::Mutex::new(log));
+    let log = &mut logger;
+    tklog::async_infos!(log, "to mod sink");
+    assert_eq!(*lines.lock().unwrap(), vec!["to mod sink".to_string()]);
+    log.lock().await.flush().await.unwrap();
+    assert_eq!(std::fs::read_to_string("030asyncsink.log").unwrap(), "to mod sink");
+}
+
+mod syncflush {
+    pub fn testmod() {
+        for i in 0..500 {
+            tklog::info!("sync flush", i);
+        }
+    }
+}
+
+#[test]
+fn testflush() {
+    let _ = std::fs::remove_file("030flush.log");
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_level(LEVEL::Trace).set_format(Format::LevelFlag).set_fileoption(tklog::handle::FileSizeMode::new("030flush.log", 1 << 30, 0, false));
+    tklog::LOG.set_mod_option("test_0_3_0::syncflush", lo);
+    syncflush::testmod();
+    tklog::LOG.flush().unwrap();
+    let s = std::fs::read_to_string("030flush.log").unwrap();
+    assert_eq!(s.lines().count(), 500);
+}
+
+mod asyncflush {
+    pub async fn testmod() {
+        for i in 0..500 {
+            tklog::async_info!("async flush", i);
+        }
+    }
+}
+
+#[tokio::test]
+async fn testasyncshutdown() {
+    let _ = std::fs::remove_file("030asyncflush.log");
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_level(LEVEL::Trace).set_format(Format::LevelFlag).set_fileoption(tklog::handle::FileSizeMode::new("030asyncflush.log", 1 << 30, 0, false));
+    tklog::ASYNC_LOG.set_mod_option("test_0_3_0::asyncflush", lo).await;
+    asyncflush::testmod().await;
+    tklog::ASYNC_LOG.shutdown().await.unwrap();
+    let s = std::fs::read_to_string("030asyncflush.log").unwrap();
+    assert_eq!(s.lines().count(), 500);
+
+    asyncflush::testmod().await;
+    tklog::ASYNC_LOG.flush().await.unwrap();
+    let s = std::fs::read_to_string("030asyncflush.log").unwrap();
+    assert_eq!(s.lines().count(), 1000);
+}
+
+fn queue_written(capacity: usize, overflow: tklog::OVERFLOW, name: &str) -> (u64, u64) {
+    let filename = format!("030queue{}.log", name);
+    let module = format!("test_0_3_0::queue::{}", name);
+    let _ = std::fs::remove_file(&filename);
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_level(LEVEL::Trace).set_format(Format::LevelFlag).set_fileoption(tklog::handle::FileSizeMode::new(&filename, 1 << 30, 0, false));
+    tklog::LOG.set_mod_option(&module, lo);
+    let mut log = Logger::new();
+    log.set_queue(capacity, overflow);
+    for i in 0..5000 {
+        log.log(if i % 2 == 0 { LEVEL::Info } else { LEVEL::Error }, module.clone(), format!("{}\n", i));
+    }
+    log.flush().unwrap();
+    tklog::LOG.flush().unwrap();
+    let written = std::fs::read_to_string(&filename).unwrap().lines().count() as u64;
+    (written, log.dropped())
+}
+
+#[test]
+fn testqueue() {
+    assert_eq!(queue_written(4, tklog::OVERFLOW::Block, "block"), (5000, 0));
+
+    let (written, dropped) = queue_written(4, tklog::OVERFLOW::DropNewest, "newest");
+    assert!(dropped > 0);
+    assert_eq!(written + dropped, 5000);
+
+    let (written, dropped) = queue_written(4, tklog::OVERFLOW::DropOldest, "oldest");
+    assert!(dropped > 0);
+    assert_eq!(written + dropped, 5000);
+
+    let (written, dropped) = queue_written(4, tklog::OVERFLOW::DropBelow(LEVEL::Error), "below");
+    assert!(dropped > 0 && dropped <= 2500);
+    assert_eq!(written + dropped, 5000);
+    let errors = std::fs::read_to_string("030queuebelow.log").unwrap().lines().filter(|l| l.parse::<u32>().unwrap() % 2 == 1).count();
+    assert_eq!(errors, 2500);
+}
+
+#[tokio::test]
+async fn testasyncqueue() {
+    let mut log = tklog::Async::Logger::new();
+    log.set_queue(4, tklog::OVERFLOW::DropNewest).await;
+    for i in 0..5000 {
+        log.log(LEVEL::Trace, "test_0_3_0::asyncqueue".to_string(), format!("{}", i));
+    }
+    log.flush().await.unwrap();
+    assert!(log.dropped() > 0);
+}
+
+mod asyncsend {
+    use std::sync::Arc;
+
+    pub async fn testmod(i: i32, mut logger: Arc<tokio::sync::Mutex<tklog::Async::Logger>>) {
+        tklog::async_info!("spawned task", i);
+        tklog::async_infos!(&mut logger, "spawned task with own logger", i);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn testasyncsend() {
+    let _ = std::fs::remove_file("030asyncsend.log");
+    tklog::ASYNC_LOG.set_mod_option("test_0_3_0::asyncsend", tklog::LogOption { console: Some(false), fileoption: Some(Box::new(tklog::handle::FileTimeMode::new("030asyncsend.log", tklog::MODE::DAY, 0, false))), ..Default::default() }).await;
+    let logger = std::sync::Arc::new(tokio::sync::Mutex::new(tklog::Async::Logger::new()));
+    logger.lock().await.set_console(false);
+    let tasks: Vec<_> = (0..8).map(|i| tokio::spawn(asyncsend::testmod(i, logger.clone()))).collect();
+    for t in tasks {
+        t.await.unwrap();
+    }
+    tklog::ASYNC_LOG.flush().await.unwrap();
+    let s = std::fs::read_to_string("030asyncsend.log").unwrap();
+    assert_eq!(s.lines().filter(|l| l.contains("spawned task")).count(), 8);
+}
+
+#[test]
+fn testsyncthreads() {
+    let _ = std::fs::remove_file("030syncthreads.log");
+    tklog::LOG.set_mod_option("test_0_3_0::syncthreads", tklog::LogOption { console: Some(false), fileoption: Some(Box::new(tklog::handle::FileTimeMode::new("030syncthreads.log", tklog::MODE::DAY, 0, false))), ..Default::default() });
+    let threads: Vec<_> = (0..8).map(|i| std::thread::spawn(move || syncthreads::testmod(i))).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    tklog::LOG.flush().unwrap();
+    let s = std::fs::read_to_string("030syncthreads.log").unwrap();
+    assert_eq!(s.lines().count(), 800);
+}
+
+mod syncthreads {
+    pub fn testmod(i: i32) {
+        for j in 0..100 {
+            tklog::info!("thread", i, j);
+        }
+    }
+}
+
+#[test]
+fn testdirectives() {
+    let directives = tklog::parse_directives("info, hyper=warn ,my_app::db=trace,my_app,,").unwrap();
+    assert_eq!(directives.len(), 4);
+    assert_eq!(directives[0], tklog::Directive { module: None, level: LEVEL::Info });
+    assert_eq!(directives[1], tklog::Directive { module: Some("hyper".to_string()), level: LEVEL::Warn });
+    assert_eq!(directives[2], tklog::Directive { module: Some("my_app::db".to_string()), level: LEVEL::Trace });
+    assert_eq!(directives[3], tklog::Directive { module: Some("my_app".to_string()), level: LEVEL::Trace });
+    assert_eq!(tklog::parse_directives("info,hyper=loud"), Err("hyper=loud".to_string()));
+    assert_eq!(tklog::parse_directives("=warn"), Err("=warn".to_string()));
+
+    let mut log = Logger::new();
+    log.set_mod_option("my_app", tklog::LogOption { console: Some(false), ..Default::default() });
+    log.set_directives(&tklog::parse_directives("error,hyper=warn,my_app::db=trace").unwrap());
+    assert_eq!(log.get_level("app"), LEVEL::Error);
+    assert_eq!(log.get_level("hyper"), LEVEL::Warn);
+    assert_eq!(log.get_level("hyper::client::pool"), LEVEL::Warn);
+    assert_eq!(log.get_level("my_app::db::query"), LEVEL::Trace);
+    assert_eq!(log.get_level("my_app"), LEVEL::Error);
+}
+
+#[test]
+fn testconfig() {
+    let _ = std::fs::remove_file("030config.log");
+    std::fs::write(
+        "030config.toml",
+        r#"
+level = "info"
+directives = "hyper=warn"
+console = false
+format = ["LevelFlag"]
+formatter = "{level}{message}\n"
+printmode = "punctual"
+separator = ","
+
+[file]
+path = "030config.log"
+max_size = 1048576
+
+[modules."app::db"]
+level = "trace"
+
+[levels.error]
+formatter = "E:{message}\n"
+"#,
+    )
+    .unwrap();
+    let mut log = Logger::new();
+    log.load_config("030config.toml").unwrap();
+    assert_eq!(log.get_level("app"), LEVEL::Info);
+    assert_eq!(log.get_level("app::db::pool"), LEVEL::Trace);
+    assert_eq!(log.get_level("hyper::client"), LEVEL::Warn);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "a", 1);
+    tklog::debugs!(log, "hidden");
+    tklog::errors!(log, "b", 2);
+    log.lock().unwrap().flush().unwrap();
+    assert_eq!(std::fs::read_to_string("030config.log").unwrap(), "[INFO]a,1\nE:b,2\n");
+
+    let err = |s: &str| tklog::config::Config::from_toml(s).unwrap_err().to_string();
+    assert!(err("level = \"loud\"").contains("unknown level \"loud\""));
+    assert!(err("colour = true").contains("colour"));
+    assert!(err("format = [\"Bold\"]").contains("unknown format flag \"Bold\""));
+    assert!(err("directives = \"a=b\"").contains("invalid directive \"a=b\""));
+    assert!(err("[file]\npath = \"x.log\"\nmode = \"fortnight\"").contains("unknown rotation mode \"fortnight\""));
+    assert!(err("[file]\npath = \"x.log\"\ncodec = \"brotli\"").contains("unknown codec \"brotli\""));
+    #[cfg(not(feature = "zstd"))]
+    assert!(err("[file]\npath = \"x.log\"\ncodec = \"zstd\"").contains("needs the \"zstd\" feature"));
+    assert!(tklog::config::Config::from_toml("[file]\npath = \"x.log\"\ncodec = \"gzip\"\ncompress_level = 9").is_ok());
+    assert!(err("[file]\npath = \"x.log\"\nmax_age = \"2w\"").contains("invalid age \"2w\""));
+    let c = tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nmax_total_size = 1024\nmax_age = \"14d\"\ncheck_interval = 10").unwrap();
+    assert_eq!(c.file.as_ref().unwrap().max_age, Some(tklog::config::Age(Duration::from_secs(14 * 86400))));
+    assert_eq!(c.file.unwrap().check_interval, Some(tklog::config::Age(Duration::from_secs(10))));
+    assert_eq!(tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nstartup = \"per_run\"").unwrap().file.unwrap().startup, Some(tklog::STARTUP::PERRUN));
+    assert!(err("[file]\npath = \"x.log\"\nstartup = \"never\"").contains("unknown startup \"never\""));
+    let f = tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nmin_free_space = 1048576\nlow_space_level = \"warn\"").unwrap().file.unwrap();
+    assert_eq!((f.min_free_space, f.low_space_level), (Some(1 << 20), Some(LEVEL::Warn)));
+    let f = tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nfallback = \"stderr\"\nfallback_retry = \"5s\"").unwrap().file.unwrap();
+    assert_eq!((f.fallback.as_deref(), f.fallback_retry), (Some("stderr"), Some(tklog::config::Age(Duration::from_secs(5)))));
+    let f = tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nbuffer_size = 4096\nflush_interval = \"1s\"\nsync_level = \"error\"").unwrap().file.unwrap();
+    assert_eq!((f.buffer_size, f.flush_interval, f.sync_every, f.sync_level), (4096, Some(tklog::config::Age(Duration::from_secs(1))), 0, Some(LEVEL::Error)));
+    let invalid = |s: &str| Logger::new().apply_config(&tklog::config::Config::from_toml(s).unwrap()).err().unwrap().to_string();
+    assert!(invalid("[file]\npath = \"x.log\"\nbackup_name = \"{stem}.{week}\"").contains("file: unknown placeholder {week}"));
+    assert!(invalid("[file]\npath = \"x.log\"\nbackup_name = \"backup.log\"").contains("has no placeholder"));
+    assert!(invalid("[file]\npath = \"x.log\"\nfallback = \"x.log\"").contains("file: fallback must differ from path"));
+    assert!(invalid("[file]\npath = \"x.log\"\nfallback_threshold = 5").contains("need fallback"));
+    assert!(invalid("[file]\npath = \"x.log\"\nlow_space_level = \"warn\"").contains("need min_free_space"));
+    let invalid = tklog::config::Config::from_toml("[modules.app.file]\npath = \"x.log\"\nmax_size = 0").unwrap();
+    let e = Logger::new().apply_config(&invalid).err().unwrap().to_string();
+    assert_eq!(e, "invalid log config: modules.\"app\".file: max_size must be greater than 0");
+    assert!(matches!(Logger::new().load_config("030missing.toml"), Err(tklog::config::ConfigError::Io(..))));
+    assert!(matches!(Logger::new().load_config("LICENSE"), Err(tklog::config::ConfigError::Invalid(..))));
+    std::fs::remove_file("030config.toml").unwrap();
+}
+
+#[tokio::test]
+async fn testasyncconfig() {
+    std::fs::write("030asyncconfig.json", r#"{"level": "warn", "console": false, "modules": {"app": {"level": "debug", "file": {"path": "030asyncconfig.log", "mode": "hour"}}}}"#).unwrap();
+    let mut log = tklog::Async::Logger::new();
+    log.load_config("030asyncconfig.json").await.unwrap();
+    assert_eq!(log.get_level("other"), LEVEL::Warn);
+    assert_eq!(log.get_level("app::x"), LEVEL::Debug);
+    let e = log.load_config("030asyncmissing.json").await.err().unwrap().to_string();
+    assert!(e.starts_with("cannot read log config 030asyncmissing.json"));
+    std::fs::remove_file("030asyncconfig.json").unwrap();
+}
+
+#[test]
+fn testreload() {
+    std::fs::write("030reload.toml", "level = \"warn\"\n[modules.app]\nlevel = \"trace\"\n").unwrap();
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    logger.lock().unwrap().load_config("030reload.toml").unwrap();
+    assert_eq!(logger.lock().unwrap().get_level("app::db"), LEVEL::Trace);
+    let l = logger.clone();
+    let watcher = tklog::config::watch("030reload.toml", Duration::from_millis(20), move || {
+        l.lock().unwrap().reload().unwrap();
+    });
+
+    std::fs::write("030reload.toml", "level = \"error\"\n[modules.\"app::db\"]\nlevel = \"info\"\n").unwrap();
+    thread::sleep(Duration::from_millis(300));
+    {
+        let mut log = logger.lock().unwrap();
+        assert_eq!(log.get_level("other"), LEVEL::Error);
+        assert_eq!(log.get_level("app::db"), LEVEL::Info);
+        // the old module option is gone rather than kept
+        assert_eq!(log.get_level("app"), LEVEL::Error);
+        assert!(log.reload_config(&tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nmax_size = 0").unwrap()).is_err());
+        assert_eq!(log.get_level("app::db"), LEVEL::Info);
+    }
+    // changes after the watcher is dropped are not picked up
+    drop(watcher);
+    std::fs::write("030reload.toml", "level = \"trace\"\n").unwrap();
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(logger.lock().unwrap().get_level("other"), LEVEL::Error);
+    std::fs::remove_file("030reload.toml").unwrap();
+    assert!(Logger::new().reload().is_err());
+}
+
+#[tokio::test]
+async fn testasyncreload() {
+    std::fs::write("030asyncreload.toml", "level = \"warn\"\nseparator = \"|\"\n[levels.error]\nconsole = false\n").unwrap();
+    let mut log = tklog::Async::Logger::new();
+    log.load_config("030asyncreload.toml").await.unwrap();
+    assert_eq!(log.get_separator(), "|");
+    std::fs::write("030asyncreload.toml", "[modules.app]\nlevel = \"trace\"\n").unwrap();
+    log.reload().await.unwrap();
+    assert_eq!(log.get_separator(), "");
+    assert_eq!(log.get_level("app"), LEVEL::Trace);
+    std::fs::remove_file("030asyncreload.toml").unwrap();
+}
+
+// Rotates a 200 byte file a few times and returns the head of a compressed backup.
+fn rotated_backup(codec: tklog::CODEC, extension: &str) -> Vec<u8> {
+    let dir = format!("030codec_{}", extension);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/codec.log", dir), 200, 2, false).with_compression(codec, 3);
+    rotated_head(&dir, Box::new(fo), extension)
+}
+
+fn rotated_head(dir: &str, fo: Box<dyn tklog::handle::FileOption>, extension: &str) -> Vec<u8> {
+    let _ = std::fs::remove_dir_all(dir);
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(fo), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..50 {
+        infos!(log, "rotate with compression", i);
+    }
+    let names = |d: &str| -> Vec<String> { std::fs::read_dir(d).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect() };
+    let compressed = || names(dir).into_iter().filter(|n| n.ends_with(extension)).collect::<Vec<_>>();
+    // wait until every backup has been compressed
+    for _ in 0..50 {
+        if compressed().len() >= 2 && names(dir).iter().all(|n| n == "codec.log" || n.ends_with(extension)) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    thread::sleep(Duration::from_millis(200));
+    let head = std::fs::read(format!("{}/{}", dir, compressed()[0])).unwrap()[..4].to_vec();
+    let _ = std::fs::remove_dir_all(dir);
+    head
+}
+
+#[test]
+fn testcodecgzip() {
+    assert_eq!(rotated_backup(tklog::CODEC::GZIP, "gz")[..2], [0x1f, 0x8b]);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn testcodeczstd() {
+    assert_eq!(rotated_backup(tklog::CODEC::ZSTD, "zst"), [0x28, 0xb5, 0x2f, 0xfd]);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn testcodecoptiontype() {
+    let fo = tklog::handle::FileOptionType::new(tklog::CUTMODE::SIZE, tklog::MODE::DAY, "030codec_optiontype/codec.log", 200, 2, true).with_compression(tklog::CODEC::ZSTD, 3);
+    assert_eq!(rotated_head("030codec_optiontype", Box::new(fo), "zst"), [0x28, 0xb5, 0x2f, 0xfd]);
+}
+
+#[cfg(feature = "xz")]
+#[test]
+fn testcodecxz() {
+    assert_eq!(rotated_backup(tklog::CODEC::XZ, "xz"), [0xfd, 0x37, 0x7a, 0x58]);
+}
+
+#[cfg(feature = "bzip2")]
+#[test]
+fn testcodecbzip2() {
+    assert_eq!(rotated_backup(tklog::CODEC::BZIP2, "bz2")[..3], *b"BZh");
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn testcodeclz4() {
+    assert_eq!(rotated_backup(tklog::CODEC::LZ4, "lz4"), [0x04, 0x22, 0x4d, 0x18]);
+}
+
+#[test]
+fn testcompressedretention() {
+    let dir = "030retention";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+    for (i, name) in ["keep_1.log.gz", "keep_2.log.zst", "keep_3.log.xz", "keep_4.log.bz2", "keep_5.log.lz4"].iter().enumerate() {
+        let f = std::fs::File::create(format!("{}/{}", dir, name)).unwrap();
+        f.set_modified(old + Duration::from_secs(i as u64)).unwrap();
+    }
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_cutmode_by_size(&format!("{}/keep.log", dir), 50, 2, false);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..2 {
+        infos!(log, "a line that fills most of the file", i);
+    }
+    thread::sleep(Duration::from_millis(500));
+    let mut names: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, ["keep.log", "keep_5.log.lz4", "keep_6.log"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// Waits for the single gzip backup in `dir` and returns its decompressed lines.
+fn streamed_backup(dir: &str) -> Vec<String> {
+    use std::io::Read;
+    let names = || -> Vec<String> { std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect() };
+    for _ in 0..100 {
+        if names().iter().any(|n| n.ends_with(".gz")) && names().iter().all(|n| n == "stream.log" || n.ends_with(".gz")) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let backup = names().into_iter().find(|n| n.ends_with(".gz")).unwrap();
+    assert!(names().iter().all(|n| !n.ends_with(".tmp")));
+    let mut s = String::new();
+    flate2::read::GzDecoder::new(std::fs::File::open(format!("{}/{}", dir, backup)).unwrap()).read_to_string(&mut s).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+    s.lines().map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn testcompressstream() {
+    let dir = "030stream";
+    let _ = std::fs::remove_dir_all(dir);
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_cutmode_by_size(&format!("{}/stream.log", dir), 1 << 20, 0, true);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    let line = "x".repeat(1000);
+    for _ in 0..1100 {
+        infos!(log, line);
+    }
+    let lines = streamed_backup(dir);
+    assert!(lines.len() > 1000);
+    assert!(lines.iter().all(|l| *l == line));
+}
+
+#[tokio::test]
+async fn testasynccompressstream() {
+    let dir = "030asyncstream";
+    let _ = std::fs::remove_dir_all(dir);
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false);
+    log.set_cutmode_by_size(&format!("{}/stream.log", dir), 1 << 20, 0, true).await;
+    let line = "y".repeat(1000);
+    for _ in 0..1100 {
+        log.safeprint(LEVEL::Info, "test_0_3_0", &format!("{}\n", line)).await;
+    }
+    let lines = tokio::task::spawn_blocking(move || streamed_backup(dir)).await.unwrap();
+    assert!(lines.len() > 1000);
+    assert!(lines.iter().all(|l| *l == line));
+}
+
+// Creates `dir` with backups of the given sizes and ages in seconds.
+fn old_backups(dir: &str, backups: &[(&str, usize, u64)]) {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    for (name, size, age) in backups {
+        std::fs::write(format!("{}/{}", dir, name), vec![b'x'; *size]).unwrap();
+        let f = std::fs::File::options().write(true).open(format!("{}/{}", dir, name)).unwrap();
+        f.set_modified(std::time::SystemTime::now() - Duration::from_secs(*age)).unwrap();
+    }
+}
+
+fn sorted_names(dir: &str) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn testretentionsize() {
+    let dir = "030retainsize";
+    old_backups(dir, &[("size_1.log", 100, 300), ("size_2.log", 100, 200), ("size_3.log", 100, 100)]);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/size.log", dir), 50, 0, false).with_retention(250, Duration::ZERO);
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..2 {
+        infos!(log, "a line that fills most of the file", i);
+    }
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(sorted_names(dir), ["size.log", "size_2.log", "size_3.log", "size_4.log"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testretentionage() {
+    let dir = "030retainage";
+    old_backups(dir, &[("age_1.log", 10, 20 * 86400), ("age_2.log", 10, 86400)]);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/age.log", dir), 50, 10, false).with_retention(0, Duration::from_secs(14 * 86400));
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..2 {
+        infos!(log, "a line that fills most of the file", i);
+    }
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(sorted_names(dir), ["age.log", "age_2.log", "age_3.log"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncretention() {
+    let dir = "030asyncretain";
+    old_backups(dir, &[("retain_1.log", 10, 20 * 86400), ("retain_2.log", 100, 200), ("retain_3.log", 100, 100)]);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/retain.log", dir), 50, 0, false).with_retention(150, Duration::from_secs(14 * 86400));
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    for i in 0..2 {
+        log.safeprint(LEVEL::Info, "test_0_3_0", &format!("a line that fills most of the file {}\n", i)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(sorted_names(dir), ["retain.log", "retain_3.log", "retain_4.log"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testbackupname() {
+    let dir = "030backupname";
+    old_backups(dir, &[("n.log.1", 10, 300), ("n.log.2", 10, 200), ("n_1.log", 10, 100)]);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/n.log", dir), 50, 2, false).with_backup_name("{stem}.{ext}.{counter}");
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..2 {
+        infos!(log, "a line that fills most of the file", i);
+    }
+    thread::sleep(Duration::from_millis(500));
+    // n_1.log has the default naming and is not a backup under this template
+    assert_eq!(sorted_names(dir), ["n.log", "n.log.2", "n.log.3", "n_1.log"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testbackupnamedate() {
+    let dir = "030backupdate";
+    let _ = std::fs::remove_dir_all(dir);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/app.log", dir), 50, 0, false).with_backup_name("{stem}.{YYYY}-{MM}-{DD}T{hh}.{ext}");
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    for i in 0..3 {
+        infos!(log, "a line that fills most of the file", i);
+    }
+    let stamp = chrono::Local::now().format("%Y-%m-%dT%H");
+    assert_eq!(sorted_names(dir), [format!("app.{}.log", stamp), format!("app.{}.log.1", stamp), "app.log".to_string()]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncbackupname() {
+    let dir = "030asyncbackupname";
+    old_backups(dir, &[("a.log.1", 10, 300), ("a.log.2", 10, 200)]);
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/a.log", dir), 50, 2, false).with_backup_name("{stem}.{ext}.{counter}");
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    for i in 0..2 {
+        log.safeprint(LEVEL::Info, "test_0_3_0", &format!("a line that fills most of the file {}\n", i)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(sorted_names(dir), ["a.log", "a.log.2", "a.log.3"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testschedule() {
+    use chrono::NaiveDate;
+    use tklog::schedule::Schedule;
+    let at = |d: u32, h: u32, m: u32| NaiveDate::from_ymd_opt(2024, 5, d).unwrap().and_hms_opt(h, m, 0).unwrap();
+    let next = |s: &str, t| s.parse::<Schedule>().unwrap().next_after(t);
+    assert_eq!(next("*/15 * * * *", at(21, 10, 7)), Some(at(21, 10, 15)));
+    assert_eq!(next("*/15 * * * *", at(21, 10, 15)), Some(at(21, 10, 30)));
+    assert_eq!(next("30 8-18/2 * * *", at(21, 19, 0)), Some(at(22, 8, 30)));
+    // 2024-05-21 is a Tuesday
+    assert_eq!(next("0 0 * * 1", at(21, 10, 0)), Some(at(27, 0, 0)));
+    assert_eq!(next("0 0 * * 7", at(21, 10, 0)), Some(at(26, 0, 0)));
+    assert_eq!(next("0 0 25 * 5", at(21, 10, 0)), Some(at(24, 0, 0)));
+    assert_eq!(next("@daily", at(21, 10, 0)), Some(at(22, 0, 0)));
+    assert_eq!(next("0 0 30 2 *", at(21, 10, 0)), None);
+    assert!("60 * * * *".parse::<Schedule>().unwrap_err().contains("minute \"60\" is out of range 0-59"));
+    assert!("* * *".parse::<Schedule>().unwrap_err().contains("expected five fields"));
+    assert!("*/0 * * * *".parse::<Schedule>().unwrap_err().contains("invalid step"));
+
+    let err = |s: &str| tklog::config::Config::from_toml(s).unwrap_err().to_string();
+    assert!(err("[file]\npath = \"x.log\"\nmode = \"0 25 * * *\"").contains("hour \"25\" is out of range"));
+    let c = tklog::config::Config::from_toml("[file]\npath = \"x.log\"\nmode = \"0 */6 * * *\"").unwrap();
+    assert_eq!(c.file.unwrap().mode, Some(tklog::MODE::CRON("0 */6 * * *".parse().unwrap())));
+    let invalid = |s: &str| Logger::new().apply_config(&tklog::config::Config::from_toml(s).unwrap()).err().unwrap().to_string();
+    assert!(invalid("[file]\npath = \"x.log\"\nmode = \"day\"\nweek_start = \"sunday\"").contains("week_start needs mode = \"week\""));
+    assert!(invalid("[file]\npath = \"x.log\"\nmode = \"week\"\nweek_start = \"someday\"").contains("unknown week_start \"someday\""));
+}
+
+// Writes one line to a log file last modified `age` ago and returns the files afterwards.
+fn rotate_aged(dir: &str, mode: tklog::MODE, age: Duration) -> Vec<String> {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = format!("{}/t.log", dir);
+    let f = std::fs::File::create(&path).unwrap();
+    f.set_modified(std::time::SystemTime::now() - age).unwrap();
+    let mut log = Logger::new();
+    log.set_console(false).set_cutmode_by_time(&path, mode, 0, false);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "rotated by time");
+    let names = sorted_names(dir);
+    std::fs::remove_dir_all(dir).unwrap();
+    names
+}
+
+#[test]
+fn testtimemodes() {
+    let minute_ago = chrono::Local::now() - chrono::Duration::minutes(2);
+    assert_eq!(rotate_aged("030minute", tklog::MODE::MINUTE, Duration::from_secs(120)), ["t.log".to_string(), format!("t_{}_1.log", minute_ago.format("%Y%m%d%H%M"))]);
+    assert_eq!(rotate_aged("030week", tklog::MODE::WEEK(tklog::Weekday::Mon), Duration::ZERO), ["t.log"]);
+    let week_ago = (chrono::Local::now() - chrono::Duration::days(8)).date_naive();
+    let monday = week_ago - chrono::Duration::days(chrono::Datelike::weekday(&week_ago).num_days_from_monday() as i64);
+    assert_eq!(rotate_aged("030week", tklog::MODE::WEEK(tklog::Weekday::Mon), Duration::from_secs(8 * 86400)), ["t.log".to_string(), format!("t_{}_1.log", monday.format("%Y%m%d"))]);
+    assert_eq!(rotate_aged("030cron", tklog::MODE::CRON("0 0 * * *".parse().unwrap()), Duration::from_secs(2 * 86400)).len(), 2);
+    assert_eq!(rotate_aged("030cron", tklog::MODE::CRON("0 0 1 1 *".parse().unwrap()), Duration::ZERO), ["t.log"]);
+}
+
+#[test]
+fn testreopen() {
+    let dir = "030reopen";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/r.log", dir);
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_cutmode_by_size(&path, 1 << 20, 0, false);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "before");
+    // logrotate moves the file away; records keep going to the moved file until it is reopened
+    std::fs::rename(&path, format!("{}/r.log.1", dir)).unwrap();
+    infos!(log, "moved");
+    log.lock().unwrap().reopen().unwrap();
+    infos!(log, "after");
+    assert_eq!(std::fs::read_to_string(format!("{}/r.log.1", dir)).unwrap(), "before\nmoved\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "after\n");
+
+    log.lock().unwrap().rotate_now().unwrap();
+    infos!(log, "rotated");
+    assert_eq!(sorted_names(dir), ["r.log", "r.log.1", "r_1.log"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/r_1.log", dir)).unwrap(), "after\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "rotated\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncreopen() {
+    let dir = "030asyncreopen";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/r.log", dir);
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_cutmode_by_size(&path, 1 << 20, 0, false).await;
+    log.safeprint(LEVEL::Info, "test_0_3_0", "before\n").await;
+    log.flush().await.unwrap();
+    std::fs::rename(&path, format!("{}/r.log.1", dir)).unwrap();
+    log.reopen().await.unwrap();
+    log.safeprint(LEVEL::Info, "test_0_3_0", "after\n").await;
+    log.rotate_now().await.unwrap();
+    log.safeprint(LEVEL::Info, "test_0_3_0", "rotated\n").await;
+    log.flush().await.unwrap();
+    assert_eq!(sorted_names(dir), ["r.log", "r.log.1", "r_1.log"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/r.log.1", dir)).unwrap(), "before\n");
+    assert_eq!(std::fs::read_to_string(format!("{}/r_1.log", dir)).unwrap(), "after\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "rotated\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testcheckpath() {
+    let dir = "030checkpath";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/c.log", dir);
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_check_interval(Duration::from_millis(50));
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "first");
+    std::fs::remove_file(&path).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    infos!(log, "second");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+    std::fs::rename(&path, format!("{}/c.log.1", dir)).unwrap();
+    infos!(log, "third");
+    thread::sleep(Duration::from_millis(100));
+    infos!(log, "fourth");
+    assert_eq!(std::fs::read_to_string(format!("{}/c.log.1", dir)).unwrap(), "second\nthird\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasynccheckpath() {
+    let dir = "030asynccheckpath";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/c.log", dir);
+    let fo = tklog::handle::FileTimeMode::new(&path, tklog::MODE::DAY, 0, false).with_check_interval(Duration::from_millis(50));
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    log.safeprint(LEVEL::Info, "test_0_3_0", "first\n").await;
+    log.flush().await.unwrap();
+    std::fs::rename(&path, format!("{}/c.log.1", dir)).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    log.safeprint(LEVEL::Info, "test_0_3_0", "second\n").await;
+    log.flush().await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/c.log.1", dir)).unwrap(), "first\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn testcurrentlink() {
+    let dir = "030currentlink";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    // a plain file left by the renaming mode becomes the first backup
+    let path = format!("{}/l.log", dir);
+    std::fs::write(&path, "old\n").unwrap();
+    std::fs::File::options().append(true).open(&path).unwrap().set_modified(std::time::SystemTime::now() - Duration::from_secs(3600)).unwrap();
+    let option = || tklog::handle::FileSizeMode::new(&path, 50, 1, false).with_current_link();
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(option())), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    assert_eq!(std::fs::read_link(&path).unwrap(), Path::new("l_2.log"));
+    infos!(log, "a line that fills most of the file", 1);
+    thread::sleep(Duration::from_millis(20));
+    infos!(log, "a line that fills most of the file", 2);
+    thread::sleep(Duration::from_millis(500));
+    // l_1.log exceeds the one backup kept; the file being written is never counted
+    assert_eq!(sorted_names(dir), ["l.log", "l_2.log", "l_3.log"]);
+    assert_eq!(std::fs::read_link(&path).unwrap(), Path::new("l_3.log"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "a line that fills most of the file2\n");
+
+    // a new handler resumes the linked file
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(option())), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "more");
+    assert_eq!(std::fs::read_to_string(format!("{}/l_3.log", dir)).unwrap(), "a line that fills most of the file2\nmore\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn testasynccurrentlink() {
+    let dir = "030asynccurrentlink";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/l.log", dir);
+    let fo = tklog::handle::FileTimeMode::new(&path, tklog::MODE::DAY, 0, true).with_current_link().with_backup_name("{stem}_{date}.{ext}");
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    let today = chrono::Local::now().format("%Y%m%d").to_string();
+    assert_eq!(std::fs::read_link(&path).unwrap(), Path::new(&format!("l_{}.log", today)));
+    log.safeprint(LEVEL::Info, "test_0_3_0", "first\n").await;
+    log.rotate_now().await.unwrap();
+    log.safeprint(LEVEL::Info, "test_0_3_0", "second\n").await;
+    log.flush().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(std::fs::read_link(&path).unwrap(), Path::new(&format!("l_{}.log.1", today)));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+    assert_eq!(sorted_names(dir), ["l.log".to_string(), format!("l_{}.log.1", today), format!("l_{}.log.gz", today)]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// Each startup policy applies to the first handler of a path in a process, so every run uses its own name.
+fn start_with(dir: &str, name: &str, startup: tklog::STARTUP) {
+    let fo = tklog::handle::FileSizeMode::new(&format!("{}/{}.log", dir, name), 1 << 20, 0, false).with_startup(startup);
+    let mut log = Logger::new();
+    log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "run");
+}
+
+#[test]
+fn teststartup() {
+    let dir = "030startup";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/s.log", dir), "previous\n").unwrap();
+    start_with(dir, "s", tklog::STARTUP::ROTATE);
+    assert_eq!(sorted_names(dir), ["s.log", "s_1.log"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/s_1.log", dir)).unwrap(), "previous\n");
+    assert_eq!(std::fs::read_to_string(format!("{}/s.log", dir)).unwrap(), "run\n");
+    std::fs::write(format!("{}/a.log", dir), "previous\n").unwrap();
+    start_with(dir, "a", tklog::STARTUP::APPEND);
+    assert_eq!(std::fs::read_to_string(format!("{}/a.log", dir)).unwrap(), "previous\nrun\n");
+
+    // an empty file is not worth a backup
+    std::fs::write(format!("{}/e.log", dir), "").unwrap();
+    start_with(dir, "e", tklog::STARTUP::ROTATE);
+    assert_eq!(sorted_names(dir), ["a.log", "e.log", "s.log", "s_1.log"]);
+
+    start_with(dir, "p", tklog::STARTUP::PERRUN);
+    let names = sorted_names(dir);
+    assert_eq!(names.len(), 5);
+    let run = &names[2];
+    let pattern = regex::Regex::new(&format!("^p_\\d{{14}}_{}\\.log$", std::process::id())).unwrap();
+    assert!(pattern.is_match(run), "{}", run);
+    assert_eq!(std::fs::read_to_string(format!("{}/{}", dir, run)).unwrap(), "run\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncstartup() {
+    let dir = "030asyncstartup";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = format!("{}/s.log", dir);
+    std::fs::write(&path, "previous\n").unwrap();
+    let fo = tklog::handle::FileTimeMode::new(&path, tklog::MODE::DAY, 0, false).with_startup(tklog::STARTUP::ROTATE);
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    log.safeprint(LEVEL::Info, "test_0_3_0", "run\n").await;
+    log.flush().await.unwrap();
+    let backup = format!("s_{}_1.log", chrono::Local::now().format("%Y%m%d"));
+    assert_eq!(sorted_names(dir), ["s.log".to_string(), backup.clone()]);
+    assert_eq!(std::fs::read_to_string(format!("{}/{}", dir, backup)).unwrap(), "previous\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "run\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// Counts the lines in every file of `dir`, checking that no file grew past `max_size`.
+fn count_lines(dir: &str, max_size: u64) -> usize {
+    let mut lines = 0;
+    for name in sorted_names(dir) {
+        let content = std::fs::read_to_string(format!("{}/{}", dir, name)).unwrap();
+        assert!(content.len() as u64 <= max_size, "{} has {} bytes", name, content.len());
+        lines += content.lines().count();
+    }
+    lines
+}
+
+#[test]
+fn testmultiprocess() {
+    let dir = "030multiprocess";
+    let _ = std::fs::remove_dir_all(dir);
+    // two handlers with a lock descriptor each stand in for two processes
+    let workers: Vec<_> = (0..2)
+        .map(|id| {
+            thread::spawn(move || {
+                let fo = tklog::handle::FileSizeMode::new(&format!("{}/m.log", dir), 1000, 0, false).with_multi_process();
+                let mut log = Logger::new();
+                log.set_console(false).set_formatter("{message}\n").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+                let mut logger = Arc::new(Mutex::new(log));
+                let log = &mut logger;
+                for i in 0..300 {
+                    infos!(log, format!("worker {} line {:03}", id, i));
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    assert_eq!(count_lines(dir, 1000), 600);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn testasyncmultiprocess() {
+    let dir = "030asyncmultiprocess";
+    let _ = std::fs::remove_dir_all(dir);
+    let worker = |id: u32| async move {
+        let fo = tklog::handle::FileSizeMode::new(&format!("{}/m.log", dir), 1000, 0, false).with_multi_process();
+        let mut log = tklog::Async::Logger::new();
+        log.set_console(false).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+        for i in 0..300 {
+            log.safeprint(LEVEL::Info, "test_0_3_0", &format!("worker {} line {:03}\n", id, i)).await;
+        }
+        log.flush().await.unwrap();
+    };
+    let (a, b) = tokio::join!(tokio::spawn(worker(0)), tokio::spawn(worker(1)));
+    a.unwrap();
+    b.unwrap();
+    assert_eq!(count_lines(dir, 1000), 600);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn testdurability() {
+    let dir = "030durability";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/d.log", dir);
+    let open = |durability| tklog::syncfile::FileHandler::new(Box::new(tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_durability(durability))).unwrap();
+    let content = || std::fs::read_to_string(&path).unwrap();
+
+    let mut fh = open(tklog::Durability::buffered(32, Duration::ZERO));
+    fh.write(b"0123456789\n").unwrap();
+    fh.write(b"0123456789\n").unwrap();
+    assert_eq!(content(), "");
+    fh.write(b"0123456789\n").unwrap();
+    assert_eq!(content().len(), 33);
+    fh.write(b"flushed\n").unwrap();
+    fh.flush().unwrap();
+    assert!(content().ends_with("\nflushed\n"));
+    fh.write(b"dropped\n").unwrap();
+    drop(fh);
+    assert!(content().ends_with("\ndropped\n"));
+
+    std::fs::write(&path, "").unwrap();
+    let mut fh = open(tklog::Durability::buffered(1 << 20, Duration::from_millis(100)));
+    fh.write(b"first\n").unwrap();
+    thread::sleep(Duration::from_millis(150));
+    fh.write(b"second\n").unwrap();
+    assert_eq!(content(), "first\nsecond\n");
+    drop(fh);
+
+    // an error is written and synced at once, along with what was buffered before it
+    std::fs::write(&path, "").unwrap();
+    let mut fh = open(tklog::Durability::buffered(1 << 20, Duration::ZERO).with_sync_level(LEVEL::Error));
+    fh.write_level(LEVEL::Info, b"info\n").unwrap();
+    assert_eq!(content(), "");
+    fh.write_level(LEVEL::Error, b"error\n").unwrap();
+    assert_eq!(content(), "info\nerror\n");
+    drop(fh);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncdurability() {
+    let dir = "030asyncdurability";
+    let _ = std::fs::remove_dir_all(dir);
+    let path = format!("{}/d.log", dir);
+    let content = || std::fs::read_to_string(&path).unwrap();
+    let fo = tklog::handle::FileTimeMode::new(&path, tklog::MODE::DAY, 0, false).with_durability(tklog::Durability::buffered(1 << 20, Duration::ZERO).with_sync_level(LEVEL::Error));
+    let mut fh = tklog::asyncfile::FileHandler::new(Box::new(fo)).await.unwrap();
+    fh.write_level(LEVEL::Info, b"info\n").await.unwrap();
+    assert_eq!(content(), "");
+    fh.write_level(LEVEL::Error, b"error\n").await.unwrap();
+    assert_eq!(content(), "info\nerror\n");
+    fh.write(b"buffered\n").await.unwrap();
+    fh.flush().await.unwrap();
+    assert_eq!(content(), "info\nerror\nbuffered\n");
+
+    // every record is on disk when the write returns
+    let fo = tklog::handle::FileTimeMode::new(&path, tklog::MODE::DAY, 0, false).with_durability(tklog::Durability::immediate());
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false).set_formatter("{message}").set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await;
+    log.safeprint(LEVEL::Info, "test_0_3_0", "audit\n").await;
+    assert!(content().ends_with("buffered\naudit\n"), "{}", content());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+mod durabilitytimer {
+    pub fn testmod() {
+        tklog::info!("timer");
+    }
+}
+
+// the writer thread of LOG writes a due buffer while no more records arrive
+#[test]
+fn testdurabilitytimer() {
+    let _ = std::fs::remove_file("030durabilitytimer.log");
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_level(LEVEL::Trace).set_format(Format::LevelFlag).set_fileoption(
+        tklog::handle::FileSizeMode::new("030durabilitytimer.log", 1 << 30, 0, false).with_durability(tklog::Durability::buffered(1 << 20, Duration::from_millis(100))),
+    );
+    tklog::LOG.set_mod_option("test_0_3_0::durabilitytimer", lo);
+    durabilitytimer::testmod();
+    thread::sleep(Duration::from_millis(1500));
+    let s = std::fs::read_to_string("030durabilitytimer.log").unwrap();
+    assert_eq!(s.lines().count(), 1);
+}
+
+mod asyncdurabilitytimer {
+    pub async fn testmod() {
+        tklog::async_info!("timer");
+    }
+}
+
+#[tokio::test]
+async fn testasyncdurabilitytimer() {
+    let _ = std::fs::remove_file("030asyncdurabilitytimer.log");
+    let mut lo = tklog::LogOption::new();
+    lo.set_console(false).set_level(LEVEL::Trace).set_format(Format::LevelFlag).set_fileoption(
+        tklog::handle::FileSizeMode::new("030asyncdurabilitytimer.log", 1 << 30, 0, false).with_durability(tklog::Durability::buffered(1 << 20, Duration::from_millis(100))),
+    );
+    tklog::ASYNC_LOG.set_mod_option("test_0_3_0::asyncdurabilitytimer", lo).await;
+    asyncdurabilitytimer::testmod().await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let s = std::fs::read_to_string("030asyncdurabilitytimer.log").unwrap();
+    assert_eq!(s.lines().count(), 1);
+}
+
+#[test]
+fn testerrors() {
+    let dir = "030errors";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    // a regular file where the log directory should be
+    std::fs::write(format!("{}/file", dir), "").unwrap();
+    let unwritable = format!("{}/file/e.log", dir);
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut log = Logger::new();
+    let s = seen.clone();
+    log.set_console(false).set_error_handler(move |e| s.lock().unwrap().push((e.kind, e.to_string())));
+    assert!(log.try_set_cutmode_by_size(&unwritable, 1 << 20, 0, false).is_err());
+    let fo = tklog::handle::FileTimeMode::new(&unwritable, tklog::MODE::DAY, 0, false);
+    assert!(log.try_set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).is_err());
+    assert_eq!(log.errors().total(), 0);
+
+    log.set_cutmode_by_size(&unwritable, 1 << 20, 0, false);
+    let errors = log.errors();
+    assert_eq!((errors.open, errors.total()), (1, 1));
+    assert!(errors.last.unwrap().1.starts_with(&format!("tklog failed to open {}: ", unwritable)));
+    assert_eq!(seen.lock().unwrap()[0].0, tklog::FAILURE::OPEN);
+
+    // a rotation whose rename fails is reported, and retried with the next record
+    let rotating = format!("{}/r.log", dir);
+    log.set_cutmode_by_size(&rotating, 10, 0, false).set_printmode(tklog::PRINTMODE::PUNCTUAL);
+    std::fs::remove_file(&rotating).unwrap();
+    log.safeprint(LEVEL::Info, "test_0_3_0", "past the size limit\n");
+    log.safeprint(LEVEL::Info, "test_0_3_0", "past the size limit\n");
+    assert_eq!(log.errors().rotate, 2);
+    assert!(!Path::new(&format!("{}/r_1.log", dir)).exists());
+
+    #[cfg(target_os = "linux")]
+    {
+        log.set_cutmode_by_size("/dev/full", 1 << 20, 0, false).set_printmode(tklog::PRINTMODE::PUNCTUAL);
+        let mut logger = Arc::new(Mutex::new(log));
+        let log = &mut logger;
+        infos!(log, "lost");
+        infos!(log, "lost");
+        assert_eq!(log.lock().unwrap().errors().write, 2);
+        assert!(seen.lock().unwrap()[3].1.starts_with("tklog failed to write /dev/full: "));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn testasyncerrors() {
+    let dir = "030asyncerrors";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/file", dir), "").unwrap();
+    let unwritable = format!("{}/file/e.log", dir);
+
+    let mut log = tklog::Async::Logger::new();
+    log.set_console(false);
+    assert!(log.try_set_cutmode_by_time(&unwritable, tklog::MODE::DAY, 0, false).await.is_err());
+    log.set_cutmode_by_time(&unwritable, tklog::MODE::DAY, 0, false).await;
+    assert_eq!(log.errors().open, 1);
+
+    #[cfg(target_os = "linux")]
+    {
+        let fo = tklog::handle::FileSizeMode::new("/dev/full", 1 << 20, 0, false);
+        log.try_set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() }).await.unwrap();
+        // tokio completes a write in the background, so its error comes with the next one
+        log.safeprint(LEVEL::Info, "test_0_3_0", "lost\n").await;
+        log.safeprint(LEVEL::Info, "test_0_3_0", "lost\n").await;
+        assert_eq!(log.errors().write, 1);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn testfailover() {
+    let dir = "030failover";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = format!("{}/p.log", dir);
+    let fallback = format!("{}/fallback.log", dir);
+    // writes to the log file fail while it links to /dev/full
+    std::os::unix::fs::symlink("/dev/full", &path).unwrap();
+    let failover = tklog::Failover::new(tklog::FALLBACK::FILE(fallback.clone())).with_threshold(2).with_retry(Duration::from_millis(100), Duration::from_secs(1));
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_failover(failover);
+    let mut fh = tklog::syncfile::FileHandler::new(Box::new(fo)).unwrap();
+    assert!(fh.write(b"lost\n").is_err());
+    fh.write(b"first\n").unwrap();
+    fh.write(b"second\n").unwrap();
+    thread::sleep(Duration::from_millis(150));
+    // the retry fails and the next one waits twice as long
+    fh.write(b"third\n").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, "").unwrap();
+    thread::sleep(Duration::from_millis(150));
+    fh.write(b"fourth\n").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    thread::sleep(Duration::from_millis(100));
+    fh.write(b"recovered\n").unwrap();
+    fh.write(b"after\n").unwrap();
+
+    assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "first\nsecond\nthird\nfourth\n");
+    let content = std::fs::read_to_string(&path).unwrap();
+    let (marker, rest) = content.split_once('\n').unwrap();
+    assert!(marker.starts_with(&format!("tklog: {} could not be written from ", path)), "{}", marker);
+    assert!(marker.ends_with(&format!("; 4 records went to {}", fallback)), "{}", marker);
+    assert_eq!(rest, "recovered\nafter\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// records still buffered when the file fails go to the fallback along with the one that failed
+#[cfg(target_os = "linux")]
+#[test]
+fn testfailoverbuffered() {
+    let dir = "030failoverbuffered";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = format!("{}/p.log", dir);
+    let fallback = format!("{}/fallback.log", dir);
+    std::os::unix::fs::symlink("/dev/full", &path).unwrap();
+    let failover = tklog::Failover::new(tklog::FALLBACK::FILE(fallback.clone())).with_threshold(2);
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_durability(tklog::Durability::buffered(16, Duration::ZERO)).with_failover(failover);
+    let mut fh = tklog::syncfile::FileHandler::new(Box::new(fo)).unwrap();
+    fh.write(b"first\n").unwrap();
+    assert!(fh.write(b"second record\n").is_err());
+    fh.write(b"third\n").unwrap();
+    assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "first\nsecond record\nthird\n");
+    drop(fh);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn testasyncfailover() {
+    let dir = "030asyncfailover";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = format!("{}/p.log", dir);
+    let fallback = format!("{}/fallback.log", dir);
+    std::os::unix::fs::symlink("/dev/full", &path).unwrap();
+    let failover = tklog::Failover::new(tklog::FALLBACK::FILE(fallback.clone())).with_threshold(1).with_retry(Duration::from_millis(100), Duration::from_secs(1));
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_failover(failover);
+    let mut fh = tklog::asyncfile::FileHandler::new(Box::new(fo)).await.unwrap();
+    // the failure of a write shows with the next one, which goes to the fallback
+    fh.write(b"lost\n").await.unwrap();
+    fh.write(b"first\n").await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, "").unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    fh.write(b"recovered\n").await.unwrap();
+    fh.flush().await.unwrap();
+
+    assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "first\n");
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.ends_with(&format!("; 1 record went to {}\nrecovered\n", fallback)), "{}", content);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// Backups of the guarded log file, the older first, and a file that is not one.
+#[cfg(unix)]
+fn diskguard_dir(dir: &str) {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    for (i, days) in [(1, 3), (2, 2)] {
+        let backup = std::fs::File::create(format!("{}/g_{}.log", dir, i)).unwrap();
+        backup.set_modified(std::time::SystemTime::now() - Duration::from_secs(days * 86400)).unwrap();
+    }
+    std::fs::write(format!("{}/notes.txt", dir), "").unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn testdiskguard() {
+    let dir = "030diskguard";
+    diskguard_dir(dir);
+    let path = format!("{}/g.log", dir);
+    // no volume has this much space, so every backup goes and minor records are dropped
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_disk_guard(tklog::DiskGuard::new(u64::MAX).with_level(LEVEL::Warn));
+    let mut log = Logger::new();
+    log.set_console(false).set_printmode(tklog::PRINTMODE::PUNCTUAL).set_option(tklog::LogOption { fileoption: Some(Box::new(fo)), ..Default::default() });
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "minor");
+    tklog::warns!(log, "kept");
+    tklog::errors!(log, "kept too");
+    infos!(log, "minor");
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("kept") && content.contains("kept too") && !content.contains("minor"), "{}", content);
+    assert!(!Path::new(&format!("{}/g_1.log", dir)).exists() && !Path::new(&format!("{}/g_2.log", dir)).exists());
+    assert!(Path::new(&format!("{}/notes.txt", dir)).exists());
+    let errors = log.lock().unwrap().errors();
+    assert_eq!((errors.space, errors.dropped, errors.total()), (1, 2, 1));
+    assert!(errors.last.unwrap().1.starts_with(&format!("tklog failed to keep free space for {}: ", path)));
+
+    // the file handler as a sink gets the level of each record, and raw writes have none
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_disk_guard(tklog::DiskGuard::new(u64::MAX));
+    let mut fh = tklog::syncfile::FileHandler::new(Box::new(fo)).unwrap();
+    tklog::handle::Sink::write(&mut fh, LEVEL::Warn, "sink minor\n").unwrap();
+    tklog::handle::Sink::write(&mut fh, LEVEL::Error, "sink kept\n").unwrap();
+    fh.write(b"raw\n").unwrap();
+    fh.flush().unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.ends_with("sink kept\n") && !content.contains("sink minor") && !content.contains("raw"), "{}", content);
+
+    // with enough space nothing is pruned or dropped
+    diskguard_dir(dir);
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_disk_guard(tklog::DiskGuard::new(1));
+    let mut fh = tklog::syncfile::FileHandler::new(Box::new(fo)).unwrap();
+    fh.write_level(LEVEL::Debug, b"minor\n").unwrap();
+    fh.flush().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "minor\n");
+    assert!(Path::new(&format!("{}/g_1.log", dir)).exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn testasyncdiskguard() {
+    let dir = "030asyncdiskguard";
+    diskguard_dir(dir);
+    let path = format!("{}/g.log", dir);
+    let fo = tklog::handle::FileSizeMode::new(&path, 1 << 20, 0, false).with_disk_guard(tklog::DiskGuard::new(u64::MAX));
+    let mut fh = tklog::asyncfile::FileHandler::new(Box::new(fo)).await.unwrap();
+    fh.write_level(LEVEL::Warn, b"minor\n").await.unwrap();
+    fh.write_level(LEVEL::Error, b"kept\n").await.unwrap();
+    // a raw write has no level, so it goes with the minor records
+    fh.write(b"raw\n").await.unwrap();
+    tklog::handle::AsyncSink::write(&mut fh, LEVEL::Info, "sink minor\n").await.unwrap();
+    tklog::handle::AsyncSink::write(&mut fh, LEVEL::Fatal, "sink kept\n").await.unwrap();
+    fh.flush().await.unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "kept\nsink kept\n");
+    assert!(!Path::new(&format!("{}/g_1.log", dir)).exists() && !Path::new(&format!("{}/g_2.log", dir)).exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn teststartupreload() {
+    let dir = "030startupreload";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/r.log", dir), "previous\n").unwrap();
+    let config = tklog::config::Config::from_toml(&format!("formatter = \"{{message}}\\n\"\n[file]\npath = \"{}/r.log\"\nmax_size = 1048576\nstartup = \"rotate\"\n[modules.perrun]\nfile = {{ path = \"{}/p.log\", max_size = 1048576, startup = \"per_run\" }}", dir, dir)).unwrap();
+    let mut log = Logger::new();
+    log.set_console(false).apply_config(&config).unwrap();
+    log.safeprint(LEVEL::Info, "main", "first\n");
+    log.safeprint(LEVEL::Info, "perrun", "first\n");
+    // a reload neither rotates the live file again nor starts another per-run file
+    log.reload_config(&config).unwrap();
+    log.set_console(false);
+    log.safeprint(LEVEL::Info, "main", "second\n");
+    log.safeprint(LEVEL::Info, "perrun", "second\n");
+    log.flush().unwrap();
+
+    let names = sorted_names(dir);
+    assert_eq!(names.len(), 3, "{:?}", names);
+    assert_eq!(std::fs::read_to_string(format!("{}/r_1.log", dir)).unwrap(), "previous\n");
+    assert_eq!(std::fs::read_to_string(format!("{}/r.log", dir)).unwrap(), "first\nsecond\n");
+    let run = names.iter().find(|n| n.starts_with("p_")).unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/{}", dir, run)).unwrap(), "first\nsecond\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
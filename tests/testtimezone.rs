@@ -0,0 +1,50 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{FixedOffset, Utc};
+use tklog::{infos, sync::Logger, Format, LEVEL, MODE, TIMEZONE};
+
+// The timezone is process-wide, so everything that changes it runs in this one test.
+#[test]
+fn testtimezone() {
+    assert_eq!("+08:00".parse(), Ok(TIMEZONE::OFFSET(8 * 3600)));
+    assert_eq!("-0530".parse(), Ok(TIMEZONE::OFFSET(-(5 * 3600 + 30 * 60))));
+    assert_eq!("+8".parse(), Ok(TIMEZONE::OFFSET(8 * 3600)));
+    assert_eq!("UTC".parse(), Ok(TIMEZONE::UTC));
+    assert_eq!("local".parse(), Ok(TIMEZONE::LOCAL));
+    assert!("+25:00".parse::<TIMEZONE>().unwrap_err().contains("invalid timezone \"+25:00\""));
+
+    let offset = FixedOffset::east_opt(8 * 3600).unwrap();
+    tklog::set_timezone(TIMEZONE::OFFSET(8 * 3600));
+
+    let mut log = Logger::new();
+    log.set_format(Format::Date | Format::Time).set_formatter("{time}");
+    let before = Utc::now().with_timezone(&offset).format("%Y-%m-%d %H").to_string();
+    let s = log.fmt("app", LEVEL::Info, "", 0, String::new());
+    let after = Utc::now().with_timezone(&offset).format("%Y-%m-%d %H").to_string();
+    assert!(s.starts_with(&before) || s.starts_with(&after), "{}", s);
+
+    // the backup takes the date of the file's modification time in the configured timezone
+    let dir = "tzrotate";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(2 * 86400);
+    std::fs::File::create(format!("{}/t.log", dir)).unwrap().set_modified(modified).unwrap();
+    let mut log = Logger::new();
+    log.set_console(false).set_cutmode_by_time(&format!("{}/t.log", dir), MODE::DAY, 0, false);
+    let mut logger = Arc::new(Mutex::new(log));
+    let log = &mut logger;
+    infos!(log, "rotated");
+    let day = chrono::DateTime::<Utc>::from(modified).with_timezone(&offset).format("%Y%m%d");
+    assert!(std::path::Path::new(&format!("{}/t_{}_1.log", dir, day)).exists());
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let config = tklog::config::Config::from_toml("timezone = \"utc\"").unwrap();
+    Logger::new().apply_config(&config).unwrap();
+    assert_eq!(tklog::timezone(), TIMEZONE::UTC);
+    assert!(tklog::config::Config::from_toml("timezone = \"mars\"").unwrap_err().to_string().contains("invalid timezone \"mars\""));
+
+    tklog::set_timezone(TIMEZONE::LOCAL);
+}
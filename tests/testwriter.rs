@@ -0,0 +1,28 @@
+// Reads the threads of the process from /proc.
+#![cfg(target_os = "linux")]
+
+use std::{thread, time::Duration};
+
+use tklog::OVERFLOW;
+
+// Counts the threads of the whole process, so it runs as the only test of its binary.
+#[test]
+fn testwriterstops() {
+    let threads = || std::fs::read_dir("/proc/self/task").unwrap().count();
+    let before = threads();
+    for _ in 0..10 {
+        drop(tklog::sync::Logger::new());
+        let mut log = tklog::sync::Logger::new();
+        log.set_queue(4, OVERFLOW::DropOldest);
+        drop(log);
+        drop(tklog::Async::Logger::new());
+    }
+    // the writers stop once their loggers are gone
+    for _ in 0..50 {
+        if threads() <= before {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(threads(), before);
+}